@@ -53,6 +53,16 @@ pub fn derive_into_serialized(input: TokenStream) -> TokenStream {
 	gen.into()
 }
 
+#[proc_macro_derive(OnClick)]
+pub fn derive_on_click(input: TokenStream) -> TokenStream {
+	let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+	let name = &ast.ident;
+	let gen = quote::quote! {
+		impl OnClick for #name {}
+	};
+	gen.into()
+}
+
 /// TryFromCaptures
 ///
 /// A very contrived macro which will implement `TryFrom<regex::Captures<'_>>` on a given struct.
@@ -123,7 +133,8 @@ pub fn derive_try_from_captures(input: TokenStream) -> TokenStream {
 
 /// Add common block fields to a struct
 ///
-/// Available fields are `alpha` and `period`. A serde default will be used which uses the function
+/// Available fields are `alpha`, `period` and `format`, the latter being a
+/// `crate::blocks::format::Template`. A serde default will be used which uses the function
 /// `default_{name}`. Any function in scope that matches this pattern will be used to provide the
 /// default value.
 ///
@@ -174,6 +185,12 @@ pub fn with_fields(attr: TokenStream, item: TokenStream) -> TokenStream {
 						period: u64
 					}
 				}
+				"format" => {
+					quote::quote! {
+						#[serde(default = "default_format")]
+						format: crate::blocks::format::Template
+					}
+				}
 				attr => unimplemented!("unrecognised attribute '{}'", attr),
 			};
 			fields
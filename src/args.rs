@@ -1,14 +1,192 @@
 use crate::error::Error;
+use crate::output::Output;
 use std::env;
 
+pub const USAGE: &str = concat!(
+	"Rust Blocks ",
+	env!("CARGO_PKG_VERSION"),
+	"
+Lewis B. <gitlab.io/lewisbelcher>
+A simple i3blocks replacement written in Rust.
+
+USAGE:
+    rs-blocks [OPTIONS] [CONFIG]
+
+ARGS:
+    <CONFIG>    Config file to use [default: $XDG_CONFIG_HOME/rs-blocks/config.toml]
+
+OPTIONS:
+    -h, --help                 Print this help and exit
+    -V, --version              Print the version and exit
+        --check                Check the config for errors and exit
+        --once                 Print a single status line and exit
+        --block <NAME>         Only run the given block, e.g. `Cpu` or `Network/wlan0`
+        --output <OUTPUT>      The bar to write for, overriding the config: i3bar, swaybar,
+                               waybar, plain, lemonbar, dzen2 or json
+        --log-level <LEVEL>    Log verbosity, overriding the config (e.g. warn, info, debug)
+    -v, --verbose              Log more, repeat for more detail (-v info, -vv debug, -vvv trace)
+    -q, --quiet                Only log errors
+"
+);
+
+/// What the binary has been asked to do.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+	/// Run the bar, writing the i3bar protocol to stdout.
+	#[default]
+	Run,
+	/// Check the config and exit.
+	Check,
+	/// Print a single status line once every block has output something, then exit.
+	Once,
+	Help,
+	Version,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Args {
 	pub config_path: String,
+	pub mode: Mode,
+	/// Only run blocks whose name or id (`name/instance`) is this.
+	pub block: Option<String>,
+	pub output: Option<Output>,
+	pub log_level: Option<String>,
 }
 
 pub fn parse_args() -> Result<Args, Error> {
-	let mut args: Vec<String> = env::args().collect();
-	match args.len() {
-		2 => Ok(Args { config_path: args.remove(1) }),
+	parse(env::args().skip(1))
+}
+
+/// The config file used when none is given, following the XDG base directory spec.
+fn default_config_path() -> Option<String> {
+	let dir = env::var("XDG_CONFIG_HOME")
+		.ok()
+		.filter(|x| !x.is_empty())
+		.or_else(|| env::var("HOME").ok().map(|home| format!("{home}/.config")))?;
+	Some(format!("{dir}/rs-blocks/config.toml"))
+}
+
+fn verbosity(count: usize) -> &'static str {
+	match count {
+		1 => "info",
+		2 => "debug",
+		_ => "trace",
+	}
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, Error> {
+	let mut config_path = None;
+	let mut mode = Mode::Run;
+	let mut block = None;
+	let mut output = None;
+	let mut log_level = None;
+	let mut verbose = 0;
+	let mut quiet = false;
+
+	let mut set_mode = |new| match mode {
+		Mode::Run => {
+			mode = new;
+			Ok(())
+		}
 		_ => Err(Error::Usage),
+	};
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-h" | "--help" => set_mode(Mode::Help)?,
+			"-V" | "--version" => set_mode(Mode::Version)?,
+			"--check" => set_mode(Mode::Check)?,
+			"--once" => set_mode(Mode::Once)?,
+			"--block" => block = Some(args.next().ok_or(Error::Usage)?),
+			"--output" => {
+				let value = args.next().ok_or(Error::Usage)?;
+				output = Some(value.parse().map_err(|_| Error::Usage)?);
+			}
+			"--log-level" => log_level = Some(args.next().ok_or(Error::Usage)?),
+			"-q" | "--quiet" => quiet = true,
+			"--verbose" => verbose += 1,
+			_ if arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|x| x == 'v') => {
+				verbose += arg.len() - 1
+			}
+			_ if config_path.is_none() && !arg.starts_with('-') => config_path = Some(arg),
+			_ => return Err(Error::Usage),
+		}
+	}
+
+	let log_level = match (log_level, verbose, quiet) {
+		(Some(level), 0, false) => Some(level),
+		(None, 0, true) => Some("error".to_string()),
+		(None, 0, false) => None,
+		(None, count, false) => Some(verbosity(count).to_string()),
+		_ => return Err(Error::Usage),
+	};
+	Ok(Args {
+		config_path: config_path
+			.or_else(default_config_path)
+			.ok_or(Error::Usage)?,
+		mode,
+		block,
+		output,
+		log_level,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn args(args: &[&str]) -> Result<Args, Error> {
+		parse(args.iter().map(|x| x.to_string()))
+	}
+
+	#[test]
+	fn parse_log_level() {
+		let parsed = args(&["--log-level", "debug", "config.toml"]).unwrap();
+		assert_eq!(parsed.config_path, "config.toml");
+		assert_eq!(parsed.log_level.as_deref(), Some("debug"));
+		assert_eq!(args(&["config.toml"]).unwrap().log_level, None);
+		assert_eq!(
+			args(&["-vv", "c"]).unwrap().log_level.as_deref(),
+			Some("debug")
+		);
+		assert_eq!(
+			args(&["-v", "--verbose", "-v", "c"])
+				.unwrap()
+				.log_level
+				.as_deref(),
+			Some("trace")
+		);
+		assert_eq!(
+			args(&["c", "-q"]).unwrap().log_level.as_deref(),
+			Some("error")
+		);
+
+		assert!(args(&["config.toml", "--log-level"]).is_err());
+		assert!(args(&["-q", "-v", "c"]).is_err());
+		assert!(args(&["a.toml", "b.toml"]).is_err());
+	}
+
+	#[test]
+	fn parse_modes() {
+		let parsed = args(&["--once", "--block", "Network/wlan0", "c"]).unwrap();
+		assert_eq!(parsed.mode, Mode::Once);
+		assert_eq!(parsed.block.as_deref(), Some("Network/wlan0"));
+		assert_eq!(parsed.output, None);
+		let parsed = args(&["--output", "waybar", "c"]).unwrap();
+		assert_eq!(parsed.output, Some(Output::Waybar));
+		assert!(args(&["--output", "xmobar", "c"]).is_err());
+		assert_eq!(args(&["c"]).unwrap().mode, Mode::Run);
+		assert_eq!(args(&["--check", "c"]).unwrap().mode, Mode::Check);
+		assert_eq!(args(&["-h"]).unwrap().mode, Mode::Help);
+		assert!(args(&["--check", "--once", "c"]).is_err());
+		assert!(args(&["--unknown", "c"]).is_err());
+	}
+
+	#[test]
+	fn default_config() {
+		// Whether there's a default depends on the environment, but it always has the same name
+		if let Ok(parsed) = args(&[]) {
+			assert!(parsed.config_path.ends_with("/rs-blocks/config.toml"));
+		}
 	}
 }
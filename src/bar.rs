@@ -0,0 +1,206 @@
+use crate::blocks::{Attributes, BlockId, BlockResult, Serialized};
+use crate::click::{ClickEvent, Router};
+use crate::config::{BlockConfig, Config, Settings};
+use crate::output::Backend;
+use crate::supervisor;
+use crate::theme;
+use crate::Error;
+use futures::stream::{AbortHandle, Abortable};
+use futures_util::{stream::SelectAll, Stream, StreamExt};
+use indexmap::IndexMap;
+use std::pin::Pin;
+
+type BlockStream = Abortable<Pin<Box<dyn Stream<Item = BlockResult>>>>;
+
+/// A block which is currently running on the bar.
+struct Running {
+	config: BlockConfig,
+	handle: AbortHandle,
+	/// The block's latest output, which there's none of until it first yields.
+	output: Option<Serialized>,
+}
+
+/// The running set of blocks along with their latest output.
+pub struct Bar {
+	settings: Settings,
+	running: IndexMap<BlockId, Running>,
+	streams: SelectAll<BlockStream>,
+	router: Router,
+	/// The error from the last failed reload, if any.
+	config_error: Option<Serialized>,
+}
+
+impl Bar {
+	pub fn new(config: Config) -> Self {
+		let mut bar = Self {
+			settings: config.settings.clone(),
+			running: IndexMap::new(),
+			streams: SelectAll::new(),
+			router: Router::default(),
+			config_error: None,
+		};
+		bar.reload(Ok(config));
+		bar
+	}
+
+	fn start(&mut self, config: BlockConfig) -> Running {
+		let clicks = self.router.register(config.id());
+		let stream = supervisor::supervise(config.clone(), clicks, &self.settings);
+		let (stream, handle) = futures::stream::abortable(stream);
+		self.streams.push(stream);
+		Running {
+			config,
+			handle,
+			output: None,
+		}
+	}
+
+	/// Switch to a new config. Blocks whose config hasn't changed are left running so they keep
+	/// their state. If the new config couldn't be loaded, the error is shown on the bar and the
+	/// current blocks are left as they are.
+	pub fn reload(&mut self, config: Result<Config, Error>) {
+		let config = match config {
+			Ok(config) => config,
+			Err(e) => {
+				tracing::warn!(error = %e, "failed to load config");
+				let error = Error::Reload(e.to_string());
+				let result = BlockResult::error("Config", &error, &Attributes::default());
+				self.config_error = Some(result.block);
+				return;
+			}
+		};
+		tracing::info!(blocks = config.blocks.len(), "loaded config");
+		self.config_error = None;
+		self.settings = config.settings;
+		theme::set(config.theme);
+
+		let mut previous = std::mem::take(&mut self.running);
+		for block_config in config.blocks {
+			let id = block_config.id();
+			let running = match previous.shift_remove(&id) {
+				Some(running) if running.config == block_config => running,
+				Some(running) => {
+					// Keep showing the old output until the restarted block yields, to avoid the
+					// block disappearing momentarily
+					running.handle.abort();
+					Running {
+						output: running.output,
+						..self.start(block_config)
+					}
+				}
+				None => self.start(block_config),
+			};
+			self.running.insert(id, running);
+		}
+		for (id, running) in previous {
+			running.handle.abort();
+			self.router.unregister(&id);
+		}
+	}
+
+	/// The settings of the config last loaded successfully.
+	pub fn settings(&self) -> &Settings {
+		&self.settings
+	}
+
+	/// Wait for the next result from any running block.
+	pub async fn next(&mut self) -> BlockResult {
+		match self.streams.next().await {
+			Some(result) => result,
+			// There are no blocks running, so wait until a reload adds some
+			None => std::future::pending().await,
+		}
+	}
+
+	/// Record a block's latest output.
+	pub fn update(&mut self, result: BlockResult) {
+		if let Some(running) = self.running.get_mut(&result.id) {
+			running.output = Some(result.block);
+		}
+	}
+
+	pub fn dispatch(&self, event: ClickEvent) {
+		self.router.dispatch(event);
+	}
+
+	/// Whether every block has output something since it was started.
+	pub fn is_complete(&self) -> bool {
+		self.running.values().all(|x| x.output.is_some())
+	}
+
+	/// The current status line as written by `backend`. See `Backend::status_line`.
+	pub fn status_line(&self, backend: &dyn Backend, streaming: bool) -> String {
+		let blocks: Vec<_> = self
+			.config_error
+			.iter()
+			.chain(self.running.values().filter_map(|x| x.output.as_ref()))
+			.collect();
+		backend.status_line(&blocks, streaming)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::config;
+	use crate::output::I3bar;
+
+	const CONFIG: &str = "
+		[Time]
+		format = '%H'
+
+		[Cpu]
+		alpha = 0.1
+	";
+
+	fn handle(bar: &Bar, id: &str) -> AbortHandle {
+		bar.running
+			.iter()
+			.find(|(x, _)| x.to_string() == id)
+			.map(|(_, running)| running.handle.clone())
+			.unwrap()
+	}
+
+	#[test]
+	fn reload_keeps_unchanged_blocks() {
+		let mut bar = Bar::new(config::deserialise(CONFIG).unwrap());
+		let time = handle(&bar, "Time");
+		let cpu = handle(&bar, "Cpu");
+
+		let string = "
+			[Memory]
+
+			[Time]
+			format = '%H'
+
+			[Cpu]
+			alpha = 0.2
+		";
+		bar.reload(config::deserialise(string));
+		assert!(!time.is_aborted());
+		assert!(cpu.is_aborted());
+		assert!(!handle(&bar, "Cpu").is_aborted());
+		let ids: Vec<_> = bar.running.keys().map(|x| x.to_string()).collect();
+		assert_eq!(ids, vec!["Memory", "Time", "Cpu"]);
+
+		bar.reload(config::deserialise("[Cpu]\nalpha = 0.2"));
+		assert!(time.is_aborted());
+		assert_eq!(bar.running.len(), 1);
+	}
+
+	#[test]
+	fn failed_reload_keeps_blocks() {
+		let mut bar = Bar::new(config::deserialise(CONFIG).unwrap());
+		let time = handle(&bar, "Time");
+		bar.reload(config::deserialise("[Nonsense]"));
+		assert!(!time.is_aborted());
+		assert_eq!(bar.running.len(), 2);
+		assert_eq!(
+			bar.status_line(&I3bar, false),
+			r#"[{"name":"Config","full_text":"failed to reload config: no block implemented for 'Nonsense'","urgent":true}]"#
+		);
+
+		bar.reload(config::deserialise(CONFIG));
+		assert_eq!(bar.status_line(&I3bar, false), "[]");
+	}
+}
@@ -1,4 +1,7 @@
-use crate::blocks::{default_period, prelude::*, util, StreamExt2};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::thresholds::{Rgb, State, Thresholds};
+use crate::blocks::{default_period, prelude::*, util, Update};
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
 use futures_util::{pin_mut, Stream, StreamExt};
@@ -6,22 +9,50 @@ use rs_blocks_macros::*;
 use serde::Deserialize;
 use std::fmt;
 use std::time::Instant;
-use tokio::time::{sleep, Duration};
+use tokio::time::Duration;
+
+/// The values available to `format` and `short_format`. `status` is the estimated time remaining
+/// while charging or discharging and the battery's state otherwise, whereas `remaining` is only
+/// present once there's an estimate.
+const VARIABLES: &[&str] = &["icon", "percent", "status", "remaining"];
 
 // Add a derive macro with customisable defaults for name and period etc. Or separate derives for
 // default name, default period etc?
-#[with_fields(alpha, period)]
-#[derive(Debug, Deserialize, GetName, PangoMarkup, IntoSerialized)]
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, GetName, PangoMarkup, IntoSerialized, OnClick)]
 pub struct Battery {
 	path_to_charge_now: String,
 	path_to_charge_full: String,
 	path_to_status: String,
+	#[serde(default = "default_short_format")]
+	short_format: Template,
 }
 
 fn default_alpha() -> f32 {
 	0.05
 }
 
+fn default_format() -> Template {
+	"{icon} {percent:.0}% ({status})".parse().unwrap()
+}
+
+fn default_short_format() -> Template {
+	"{icon} {percent:.0}%".parse().unwrap()
+}
+
+/// Low charge is bad, shading from red when empty to green when full.
+pub fn default_thresholds() -> Thresholds {
+	Thresholds {
+		warning: Some(20.0),
+		critical: Some(10.0),
+		gradient: Some(vec![
+			(0.0, Rgb(0xff, 0x00, 0x00)),
+			(50.0, Rgb(0xff, 0xff, 0x00)),
+			(100.0, Rgb(0x00, 0xff, 0x00)),
+		]),
+	}
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Status {
 	Charging(Remaining),
@@ -41,6 +72,15 @@ impl Status {
 			Status::Unknown => {}
 		};
 	}
+
+	/// The estimated minutes remaining, if there is an estimate yet.
+	fn remaining(&self) -> Option<f32> {
+		match self {
+			Self::Charging(Remaining::Minutes(ema))
+			| Self::Discharging(Remaining::Minutes(ema)) => ema.into(),
+			_ => None,
+		}
+	}
 }
 
 impl TryFrom<(&str, f32)> for Status {
@@ -76,37 +116,27 @@ impl fmt::Display for Status {
 	}
 }
 
-/// Given a percentage of charge, wrap the string `string` in an appropriate colour.
-fn wrap_in_colour(string: &str, fraction: f32) -> String {
-	let colour = if fraction > 0.5 {
-		format!("{:0>2x}ff00", 255 - (510.0 * (fraction - 0.5)) as i32)
-	} else {
-		format!("ff{:0>2x}00", (510.0 * fraction) as i32)
-	};
-	format!("<span foreground='#{}'>{}</span>", colour, string)
-}
-
-/// Given a percentage of charge, return an appropriate battery symbol.
-fn get_discharge_symbol(fraction: f32) -> &'static str {
+/// Given a fraction of charge, return the key of an appropriate battery icon.
+fn get_discharge_icon(fraction: f32) -> &'static str {
 	if fraction > 0.90 {
-		"  "
+		"battery_full"
 	} else if fraction > 0.60 {
-		"  "
+		"battery_three_quarters"
 	} else if fraction > 0.40 {
-		"  "
+		"battery_half"
 	} else if fraction > 0.10 {
-		"  "
+		"battery_quarter"
 	} else {
-		"  "
+		"battery_empty"
 	}
 }
 
-fn get_symbol(status: Status, fraction: f32) -> String {
-	let string = match status {
-		Status::Discharging(_) => get_discharge_symbol(fraction),
-		_ => " ",
-	};
-	wrap_in_colour(string, fraction)
+fn get_icon(status: Status, fraction: f32) -> &'static str {
+	match status {
+		Status::Discharging(_) => get_discharge_icon(fraction),
+		Status::Full => "battery_full",
+		_ => "battery_charging",
+	}
 }
 
 /// Convert a float of minutes into a string of hours and minutes.
@@ -182,31 +212,25 @@ impl fmt::Display for Remaining {
 }
 
 impl IntoStream for Battery {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		// We want to run these on the current thread and blocking during stream setup. Apparently
-		// tokio's `Handle::block_on` is error-prone when using `current_thread`. See
-		// https://docs.rs/tokio/latest/tokio/runtime/struct.Handle.html#method.block_on
-		// But `futures::executor::block_on` works and executes on the current thread.
-		let max: f32 = {
-			let future = util::read_to_ty(&self.path_to_charge_full);
-			futures::executor::block_on(future).unwrap()
-		};
-		let mut charge_fraction: f32 = {
-			let future = util::read_to_ty(&self.path_to_charge_now);
-			let charge: f32 = futures::executor::block_on(future).unwrap();
-			charge / max
-		};
-		let mut status: Status = {
-			let future = util::read_to_ty(&self.path_to_status);
-			let status_str: String = futures::executor::block_on(future).unwrap();
-			(status_str.as_str(), self.alpha).try_into().unwrap()
-		};
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)?;
+		self.short_format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let max: f32 = util::read_to_ty(&self.path_to_charge_full).await?;
+		let charge: f32 = util::read_to_ty(&self.path_to_charge_now).await?;
+		let mut charge_fraction = charge / max;
+		let status_str: String = util::read_to_ty(&self.path_to_status).await?;
+		let mut status: Status = (status_str.as_str(), self.alpha).try_into()?;
 
-		try_stream! {
-			let charge_watcher = util::watch::<_, 30>(&self.path_to_charge_now)
-				.with_period(|| sleep(Duration::from_millis(self.period)));
-			let status_watcher = util::watch::<_, 30>(&self.path_to_status)
-				.with_period(|| sleep(Duration::from_millis(self.period)));
+		Ok(try_stream! {
+			let period = Duration::from_millis(self.period);
+			let charge_watcher = util::watch(&self.path_to_charge_now, period);
+			let status_watcher = util::watch(&self.path_to_status, period);
 			pin_mut!(charge_watcher, status_watcher);
 			let mut prev_charge: Option<f32> = None;
 			let mut interval = Interval::new();
@@ -244,11 +268,20 @@ impl IntoStream for Battery {
 						new_status.map(|_| ())
 					},
 				}?;
-				let symbol = get_symbol(status, charge_fraction);
 				let percent = 100.0 * charge_fraction;
-				yield format!("{symbol} {percent:.0}% ({status})");
+				let values = Values::new()
+					.with("icon", theme::icon(get_icon(status, charge_fraction)))
+					.with("percent", percent)
+					.with("status", status.to_string())
+					.with("remaining", status.remaining().map(minutes_to_string));
+				let mut update = Update::new(self.format.render(&values)).with_value(percent);
+				if matches!(status, Status::Charging(_) | Status::Full) {
+					update = update.with_state(State::Good);
+				}
+				update.attributes.short_text = Some(self.short_format.render(&values));
+				yield update;
 			}
-		}
+		})
 	}
 }
 
@@ -262,11 +295,14 @@ mod test {
 	fn configuration() {
 		let string = "
 			[Battery]
+			path_to_charge_now = '/sys/class/power_supply/BAT0/energy_now'
+			path_to_charge_full = '/sys/class/power_supply/BAT0/energy_full'
+			path_to_status = '/sys/class/power_supply/BAT0/status'
 			period = 300
 			alpha = 0.1
 		";
-		let mut deserialised = config::deserialise(string).unwrap();
-		if let Block::Battery(battery) = deserialised.remove(0) {
+		let mut deserialised = config::deserialise(string).unwrap().blocks;
+		if let Block::Battery(battery) = deserialised.remove(0).block {
 			assert_eq!(battery.period, 300);
 			assert_eq!(battery.alpha, 0.1);
 		} else {
@@ -274,6 +310,18 @@ mod test {
 		};
 	}
 
+	#[tokio::test]
+	async fn setup_error() {
+		let string = "
+			path_to_charge_now = '/nonexistent'
+			path_to_charge_full = '/nonexistent'
+			path_to_status = '/nonexistent'
+		";
+		let battery: Battery = toml::from_str(string).unwrap();
+		let result = battery.try_into_stream().await;
+		assert!(matches!(result, Err(Error::Read { .. })));
+	}
+
 	#[test]
 	fn minutes_to_string_works() {
 		assert_eq!(minutes_to_string(302.2), "5h02m");
@@ -286,12 +334,18 @@ mod test {
 	}
 
 	#[test]
-	fn test_wrap_in_colour() {
-		let result = wrap_in_colour("a", 1.0);
-		assert_eq!(result, "<span foreground=\'#00ff00\'>a</span>");
-
-		let result = wrap_in_colour("a", 0.01);
-		assert_eq!(result, "<span foreground=\'#ff0500\'>a</span>");
+	fn default_colours() {
+		let thresholds = default_thresholds();
+		let colour = |percent: f32| {
+			let mut update = Update::new("a".to_string()).with_value(percent);
+			thresholds.apply(&mut update, &theme::Theme::default().palette);
+			update
+		};
+		let color = |percent| colour(percent).attributes.color;
+		assert_eq!(color(100.0).as_deref(), Some("#00ff00"));
+		assert_eq!(color(30.0).as_deref(), Some("#ff9900"));
+		assert_eq!(color(15.0).as_deref(), Some("#ffcc00"));
+		assert_eq!(colour(5.0).attributes.urgent, Some(true));
 	}
 
 	#[test]
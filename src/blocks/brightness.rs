@@ -1,13 +1,20 @@
-use crate::blocks::{prelude::*, util};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{prelude::*, util, Update};
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
-use futures_util::Stream;
+use futures_util::{pin_mut, Stream, StreamExt};
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use tokio::signal::unix::{signal, SignalKind};
+use std::num::ParseIntError;
+use tokio::signal::unix::SignalKind;
+use tokio::time::Duration;
 
-#[with_fields(period)]
-#[derive(Debug, Deserialize, NoMarkup, GetName, IntoSerialized)]
+/// The values available to `format`, where `icon` is the theme's `brightness` icon.
+const VARIABLES: &[&str] = &["icon", "percent"];
+
+#[with_fields(period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
 pub struct Brightness {
 	#[serde(default = "default_update_signal")]
 	update_signal: i32,
@@ -23,20 +30,51 @@ fn default_update_signal() -> i32 {
 	SignalKind::user_defined1().as_raw_value()
 }
 
+fn default_format() -> Template {
+	"{icon} {percent:.0}%".parse().unwrap()
+}
+
 impl IntoStream for Brightness {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let mut signal_stream = signal(SignalKind::from_raw(self.update_signal))
-			.expect("failed to initialise Brightness signal hook");
-		let duration = std::time::Duration::from_millis(self.period);
-		let max_brightness = self.max_brightness / 100; // Adjust for getting the percentage
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
 
-		try_stream! {
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let mut signal_stream = util::signal_stream(self.update_signal)?;
+		let period = Duration::from_millis(self.period);
+		if self.max_brightness == 0 {
+			return Err(Error::Parse {
+				ty: "u32",
+				reason: "max_brightness must be greater than 0".to_string(),
+			});
+		}
+
+		Ok(try_stream! {
+			let watcher = util::watch(&self.path_to_current_brightness, period);
+			pin_mut!(watcher);
 			loop {
-				// Ignore the Result, it's fine if the timeout elapses
-				let _ = tokio::time::timeout(duration, signal_stream.recv()).await;
-				let current: u32 = util::read_to_ty(&self.path_to_current_brightness).await?;
-				yield format!("  {:.0}%", current / max_brightness);
+				let contents = tokio::select! {
+					Some(contents) = watcher.next() => contents,
+					// Not every backlight driver notifies changes to its brightness file, so the
+					// signal can still be used to trigger an update
+					Some(()) = signal_stream.recv() => {
+						util::read_to_ty(&self.path_to_current_brightness).await
+					}
+				};
+				let current: u32 = contents?
+					.trim()
+					.parse()
+					.map_err(|e: ParseIntError| Error::Parse { ty: "u32", reason: e.to_string() })?;
+				let percent = 100.0 * current as f32 / self.max_brightness as f32;
+				let values = Values::new()
+					.with("icon", theme::icon("brightness"))
+					.with("percent", percent);
+				let text = self.format.render(&values);
+				yield Update::new(text).with_value(percent);
 			}
-		}
+		})
 	}
 }
@@ -0,0 +1,253 @@
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{pause, prelude::*, util, Update};
+use crate::click::ClickEvent;
+use crate::Error;
+use async_stream::try_stream;
+use futures_util::Stream;
+use rs_blocks_macros::*;
+use serde::Deserialize;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process;
+use tokio::signal::unix::Signal;
+use tokio::time::Duration;
+
+const SHELL: &str = "sh";
+
+/// The exit code i3blocks scripts use to mark their block as urgent.
+const URGENT_EXIT_CODE: i32 = 33;
+
+/// The values available to `format`, `text` being the full text the command printed.
+const VARIABLES: &[&str] = &["text"];
+
+/// Runs a program and displays its output, following the i3blocks conventions so that existing
+/// i3blocks scripts can be used as they are.
+///
+/// The command is run with `sh -c`. By default it's run every `period` milliseconds, with a period
+/// of 0 meaning it is only run at startup. Setting `update_signal` also runs it whenever that
+/// signal is received. With `persist` set the command is instead run once and every line it prints
+/// becomes the block's text.
+#[with_fields(period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized)]
+pub struct Command {
+	command: String,
+	#[serde(default)]
+	persist: bool,
+	#[serde(default)]
+	update_signal: Option<i32>,
+	#[serde(default)]
+	instance: Option<String>,
+}
+
+fn default_period() -> u64 {
+	5000
+}
+
+fn default_format() -> Template {
+	"{text}".parse().unwrap()
+}
+
+/// Parse the output of a command run to completion. As with i3blocks, the first three lines are
+/// the full text, short text and colour, and exiting with code 33 marks the block as urgent.
+fn parse_output(stdout: &str, urgent: bool) -> Update {
+	let mut lines = stdout.lines().map(str::trim_end);
+	let mut update = Update::new(lines.next().unwrap_or_default().to_string());
+	let mut next_line = || lines.next().filter(|x| !x.is_empty()).map(String::from);
+	update.attributes.short_text = next_line();
+	update.attributes.color = next_line();
+	if urgent {
+		update.attributes.urgent = Some(true);
+	}
+	update
+}
+
+impl Command {
+	fn build(&self) -> process::Command {
+		let mut command = process::Command::new(SHELL);
+		command
+			.args(["-c", &self.command])
+			.env("BLOCK_NAME", Self::get_name())
+			.env(
+				"BLOCK_INSTANCE",
+				self.instance.as_deref().unwrap_or_default(),
+			)
+			.kill_on_drop(true);
+		command
+	}
+
+	/// Run the command to completion, passing it the details of `click` if there is one.
+	async fn run(&self, click: Option<&ClickEvent>) -> Result<Update, Error> {
+		let mut command = self.build();
+		if let Some(click) = click {
+			command
+				.env("BLOCK_BUTTON", click.button.to_string())
+				.env("BLOCK_X", click.x.to_string())
+				.env("BLOCK_Y", click.y.to_string())
+				.env("button", click.button.to_string())
+				.env("x", click.x.to_string())
+				.env("y", click.y.to_string())
+				.env("relative_x", click.relative_x.to_string())
+				.env("relative_y", click.relative_y.to_string())
+				.env("width", click.width.to_string())
+				.env("height", click.height.to_string());
+		}
+		let output = command.stdin(Stdio::null()).output().await?;
+		let urgent = output.status.code() == Some(URGENT_EXIT_CODE);
+		if !output.status.success() && !urgent {
+			return Err(self.failure(output.status, &output.stderr));
+		}
+		let stdout = String::from_utf8(output.stdout).map_err(|e| Error::Parse {
+			ty: "UTF-8 string",
+			reason: e.to_string(),
+		})?;
+		Ok(self.render(parse_output(&stdout, urgent)))
+	}
+
+	/// Apply `format` to the full text of an update.
+	fn render(&self, mut update: Update) -> Update {
+		update.full_text = self
+			.format
+			.render(&Values::new().with("text", update.full_text));
+		update
+	}
+
+	fn failure(&self, status: ExitStatus, stderr: &[u8]) -> Error {
+		Error::Command {
+			command: self.command.clone(),
+			status,
+			stderr: String::from_utf8_lossy(stderr).trim().to_string(),
+		}
+	}
+
+	/// Wait until the command should next be run.
+	async fn wait(&self, signal: &mut Option<Signal>) {
+		let duration = Duration::from_millis(self.period);
+		match (signal, self.period) {
+			(Some(signal), 0) => pause::wait(None, signal.recv()).await,
+			(Some(signal), _) => pause::wait(Some(duration), signal.recv()).await,
+			(None, 0) => std::future::pending().await,
+			(None, _) => pause::sleep(duration).await,
+		}
+	}
+}
+
+impl IntoStream for Command {
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let mut signal = self.update_signal.map(util::signal_stream).transpose()?;
+		let mut child = if self.persist {
+			Some(
+				self.build()
+					.stdin(Stdio::null())
+					.stdout(Stdio::piped())
+					.spawn()?,
+			)
+		} else {
+			None
+		};
+
+		Ok(try_stream! {
+			if let Some(mut child) = child.take() {
+				// `stdout` is always present as it was piped above
+				let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+				while let Some(line) = lines.next_line().await? {
+					yield self.render(Update::new(line));
+				}
+				// A persistent command's stderr isn't captured, so it can't fill up a pipe while
+				// the command runs
+				let status = child.wait().await?;
+				if !status.success() {
+					Err(self.failure(status, &[]))?;
+				}
+			} else {
+				loop {
+					yield self.run(None).await?;
+					self.wait(&mut signal).await;
+				}
+			}
+		})
+	}
+}
+
+impl OnClick for Command {
+	/// Re-run the command with the click passed in the environment, as i3blocks does. Clicks on
+	/// persistent commands are ignored.
+	async fn on_click(&self, event: &ClickEvent) -> Result<Option<Update>, Error> {
+		if self.persist {
+			return Ok(None);
+		}
+		self.run(Some(event)).await.map(Some)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures_util::{pin_mut, StreamExt};
+
+	#[test]
+	fn parse_i3blocks_output() {
+		let update = parse_output("full\nshort\n#ff0000\n", false);
+		assert_eq!(update.full_text, "full");
+		assert_eq!(update.attributes.short_text.as_deref(), Some("short"));
+		assert_eq!(update.attributes.color.as_deref(), Some("#ff0000"));
+		assert_eq!(update.attributes.urgent, None);
+
+		let update = parse_output("full\n\n#00ff00", true);
+		assert_eq!(update.attributes.short_text, None);
+		assert_eq!(update.attributes.color.as_deref(), Some("#00ff00"));
+		assert_eq!(update.attributes.urgent, Some(true));
+
+		assert_eq!(parse_output("", false), Update::default());
+	}
+
+	#[tokio::test]
+	async fn run_command() {
+		let command: Command = toml::from_str(
+			r#"command = "printf '%s\n%s\n' \"$BLOCK_INSTANCE\" \"$BLOCK_BUTTON\"; exit 33"
+			instance = "a""#,
+		)
+		.unwrap();
+		let update = command.run(None).await.unwrap();
+		assert_eq!(update.full_text, "a");
+		assert_eq!(update.attributes.urgent, Some(true));
+
+		let click = ClickEvent {
+			button: 3,
+			..serde_json::from_str(r#"{"name":"Command","button":1}"#).unwrap()
+		};
+		let update = command.on_click(&click).await.unwrap().unwrap();
+		assert_eq!(update.attributes.short_text.as_deref(), Some("3"));
+	}
+
+	#[tokio::test]
+	async fn failing_command() {
+		let command: Command = toml::from_str(r#"command = "echo oops >&2; exit 1""#).unwrap();
+		let error = command.run(None).await.unwrap_err();
+		assert!(error.to_string().ends_with(": oops"), "{error}");
+	}
+
+	#[tokio::test]
+	async fn persistent_command() {
+		let command: Command = toml::from_str(
+			r#"command = "echo one; echo two"
+			persist = true
+			format = "> {text}""#,
+		)
+		.unwrap();
+		let stream = command
+			.try_into_stream()
+			.await
+			.unwrap()
+			.map(|x| x.unwrap().into().full_text);
+		pin_mut!(stream);
+		assert_eq!(stream.next().await.unwrap(), "> one");
+		assert_eq!(stream.next().await.unwrap(), "> two");
+	}
+}
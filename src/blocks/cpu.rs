@@ -1,13 +1,17 @@
-use crate::blocks::{default_alpha, default_period, prelude::*, util, StreamExt2};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{default_alpha, default_period, prelude::*, util, Update};
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
 use futures_util::Stream;
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use tokio::time::{sleep, Duration};
+use std::collections::VecDeque;
+use tokio::time::Duration;
 
-const PATTERN: &str = r"(?x)
-cpu\s+
+/// Matches the aggregate `cpu` line of `/proc/stat` and each core's `cpuN` line.
+const PATTERN: &str = r"(?mx)
+^cpu\d*\s+
 (?<user>\d+)\s+
 (?<nice>\d+)\s+
 (?<system>\d+)\s+
@@ -17,17 +21,47 @@ cpu\s+
 (?<softirq>\d+)\s+
 (?<steal>\d+)";
 
-#[with_fields(alpha, period)]
-#[derive(Debug, Deserialize, NoMarkup, GetName, IntoSerialized)]
+/// The values available to `format`, where `icon` is the theme's `cpu` icon, `cores` is a bar per
+/// core, `max_percent` is the busiest core's usage, `history` is a sparkline of `percent` and
+/// `frequency` is the mean of the cores' frequencies in GHz.
+const VARIABLES: &[&str] = &[
+	"icon",
+	"percent",
+	"cores",
+	"max_percent",
+	"history",
+	"frequency",
+];
+
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
 pub struct Cpu {
 	#[serde(default = "default_cpu_stat_path")]
 	cpu_stat_path: String,
+	/// The directory holding each core's `cpuN/cpufreq/scaling_cur_freq`.
+	#[serde(default = "default_cpufreq_path")]
+	cpufreq_path: String,
+	/// How many updates `history` shows.
+	#[serde(default = "default_history")]
+	history: usize,
 }
 
 fn default_cpu_stat_path() -> String {
 	"/proc/stat".to_string()
 }
 
+fn default_cpufreq_path() -> String {
+	"/sys/devices/system/cpu".to_string()
+}
+
+fn default_history() -> usize {
+	10
+}
+
+fn default_format() -> Template {
+	"{icon} {percent:.1}%".parse().unwrap()
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, TryFromCaptures)]
 struct CpuStats {
 	user: f32,
@@ -65,23 +99,159 @@ impl CpuStats {
 	}
 }
 
+/// The aggregate and per-core stats read from `/proc/stat`.
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+	total: CpuStats,
+	cores: Vec<CpuStats>,
+}
+
+impl Snapshot {
+	/// Parse every `cpu` line of `contents`, the first of which is the aggregate.
+	fn parse(re: &regex::Regex, contents: &str) -> Result<Self, Error> {
+		let mut stats = re.captures_iter(contents).map(CpuStats::try_from);
+		let result = stats
+			.next()
+			.unwrap_or_else(|| Err("regex pattern match failed".to_string()))
+			.and_then(|total| {
+				let cores = stats.collect::<Result<_, _>>()?;
+				Ok(Self { total, cores })
+			});
+		result.map_err(|reason| {
+			let ty = std::any::type_name::<Self>();
+			tracing::warn!(ty, pattern = re.as_str(), contents, %reason, "parse failed");
+			Error::Parse { ty, reason }
+		})
+	}
+
+	/// Each core's usage since `prev`.
+	fn core_percents(&self, prev: &Self) -> Vec<f32> {
+		self.cores
+			.iter()
+			.zip(&prev.cores)
+			.map(|(core, prev)| core.percent(*prev).unwrap_or_default())
+			.collect()
+	}
+}
+
+/// The `scaling_cur_freq` file of each core under `path`, which are missing where the kernel
+/// doesn't manage frequencies, e.g. in many VMs.
+fn frequency_paths(path: &str) -> Vec<String> {
+	let Ok(entries) = std::fs::read_dir(path) else {
+		return Vec::new();
+	};
+	entries
+		.filter_map(|entry| {
+			let entry = entry.ok()?;
+			let name = entry.file_name();
+			name.to_str()?.strip_prefix("cpu")?.parse::<u32>().ok()?;
+			let path = entry.path().join("cpufreq/scaling_cur_freq");
+			path.exists().then(|| path.to_string_lossy().into_owned())
+		})
+		.collect()
+}
+
+/// The mean of the frequencies in kHz read from `paths`, in GHz.
+async fn frequency(paths: &[String]) -> Option<f64> {
+	let mut khz = Vec::new();
+	for path in paths {
+		// A core going offline takes its file with it, which isn't worth failing the block over
+		if let Ok(x) = util::read_to_ty::<_, f64>(path).await {
+			khz.push(x);
+		}
+	}
+	(!khz.is_empty()).then(|| khz.iter().sum::<f64>() / khz.len() as f64 / 1e6)
+}
+
+/// Add `percent` to `history`, dropping the oldest values to keep at most `len` of them.
+fn push_history(history: &mut VecDeque<f32>, len: usize, percent: f32) {
+	if len == 0 {
+		return;
+	}
+	while history.len() >= len {
+		history.pop_front();
+	}
+	history.push_back(percent);
+}
+
 impl IntoStream for Cpu {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let re = regex::Regex::new(PATTERN).unwrap();
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let re = regex::Regex::new(PATTERN)?;
 		let mut ema = util::Ema::new(self.alpha);
-		let mut prev = None;
-		try_stream! {
-			let watcher = util::watch::<_, 100>(&self.cpu_stat_path)
-				.with_period(|| sleep(Duration::from_millis(self.period)));
+		let mut prev: Option<Snapshot> = None;
+		let mut history = VecDeque::with_capacity(self.history);
+		let frequency_paths = frequency_paths(&self.cpufreq_path);
+		Ok(try_stream! {
+			let watcher = util::watch(&self.cpu_stat_path, Duration::from_millis(self.period));
 			for await contents in watcher {
-				let stats: CpuStats = util::from_string(&re, &contents?)?;
-				if let Some(prev) = prev.replace(stats) {
-					if let Some(percent) = stats.percent(prev) {
-						ema.push(percent);
-						yield format!("  {:.1}%", ema);
-					}
-				}
+				let snapshot = Snapshot::parse(&re, &contents?)?;
+				let Some(prev) = prev.replace(snapshot.clone()) else {
+					continue;
+				};
+				let Some(percent) = snapshot.total.percent(prev.total) else {
+					continue;
+				};
+				let percent = ema.push(percent);
+				push_history(&mut history, self.history, percent);
+				let cores = snapshot.core_percents(&prev);
+				let max_percent = cores.iter().copied().reduce(f32::max);
+				let values = Values::new()
+					.with("icon", theme::icon("cpu"))
+					.with("percent", percent)
+					.with("cores", util::sparkline(cores))
+					.with("max_percent", max_percent)
+					.with("history", util::sparkline(history.iter().copied()))
+					.with("frequency", frequency(&frequency_paths).await);
+				let text = self.format.render(&values);
+				yield Update::new(text).with_value(percent);
 			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn snapshot(contents: &str) -> Snapshot {
+		Snapshot::parse(&regex::Regex::new(PATTERN).unwrap(), contents).unwrap()
+	}
+
+	#[test]
+	fn per_core() {
+		let prev = snapshot(
+			"cpu  10 0 10 80 0 0 0 0 0 0\ncpu0 5 0 5 40 0 0 0 0 0 0\ncpu1 5 0 5 40 0 0 0 0 0 0\nintr 1 2",
+		);
+		let next = snapshot(
+			"cpu  110 0 10 180 0 0 0 0 0 0\ncpu0 105 0 5 40 0 0 0 0 0 0\ncpu1 5 0 5 140 0 0 0 0 0 0\nintr 3 4",
+		);
+		assert_eq!(next.cores.len(), 2);
+		assert_eq!(next.total.percent(prev.total), Some(50.0));
+		assert_eq!(next.core_percents(&prev), vec![100.0, 0.0]);
+
+		let re = regex::Regex::new(PATTERN).unwrap();
+		assert!(Snapshot::parse(&re, "intr 1 2").is_err());
+		assert_eq!(frequency_paths("/nonexistent"), Vec::<String>::new());
+	}
+
+	#[test]
+	fn history() {
+		let mut history = VecDeque::new();
+		for percent in [1.0, 2.0, 3.0] {
+			push_history(&mut history, 2, percent);
 		}
+		assert_eq!(history, [2.0, 3.0]);
+
+		let mut empty = VecDeque::new();
+		push_history(&mut empty, 0, 1.0);
+		push_history(&mut empty, 0, 2.0);
+		assert!(empty.is_empty());
 	}
 }
@@ -0,0 +1,280 @@
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{pause, prelude::*, util, Update};
+use crate::theme;
+use crate::Error;
+use async_stream::try_stream;
+use futures_util::Stream;
+use itertools::Itertools;
+use nix::sys::statvfs::{statvfs, Statvfs};
+use rs_blocks_macros::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+
+/// The values available to `format`, where `icon` is the theme's `disk` icon, `percent` is the
+/// space used as `df` counts it and the rest are amounts in human units.
+const VARIABLES: &[&str] = &[
+	"icon",
+	"mount",
+	"percent",
+	"used",
+	"free",
+	"available",
+	"total",
+];
+
+/// How long to wait for a filesystem's stats, which can hang forever on an unresponsive network
+/// filesystem.
+const STATVFS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The space used on some mount points, each shown with `format` and joined with `join_with`.
+/// The block's value is the highest `percent` of them.
+#[with_fields(period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
+pub struct Disk {
+	/// The mount points to show, or every filesystem on a block device if empty.
+	#[serde(default)]
+	mount_points: Vec<String>,
+	#[serde(default = "default_mounts_path")]
+	mounts_path: String,
+	/// What goes between mount points. This can't be called `separator`, which is the i3bar
+	/// attribute.
+	#[serde(default = "default_join_with")]
+	join_with: String,
+}
+
+fn default_mounts_path() -> String {
+	"/proc/self/mounts".to_string()
+}
+
+fn default_join_with() -> String {
+	" ".to_string()
+}
+
+fn default_period() -> u64 {
+	10_000
+}
+
+fn default_format() -> Template {
+	"{icon} {mount} {percent:.0}%".parse().unwrap()
+}
+
+/// The space on a filesystem, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Usage {
+	total: f64,
+	free: f64,
+	/// The free space available to unprivileged users, which excludes the reserved blocks.
+	available: f64,
+}
+
+impl Usage {
+	fn new(stats: &Statvfs) -> Self {
+		let size = stats.fragment_size() as f64;
+		Self {
+			total: stats.blocks() as f64 * size,
+			free: stats.blocks_free() as f64 * size,
+			available: stats.blocks_available() as f64 * size,
+		}
+	}
+
+	fn used(&self) -> f64 {
+		self.total - self.free
+	}
+
+	/// The percentage used of the space available to users, as reported by `df`.
+	fn percent(&self) -> f32 {
+		let usable = self.used() + self.available;
+		match usable > 0.0 {
+			true => (100.0 * self.used() / usable) as f32,
+			false => 0.0,
+		}
+	}
+
+	fn values(&self, mount: &str) -> Values {
+		Values::new()
+			.with("icon", theme::icon("disk"))
+			.with("mount", mount)
+			.with("percent", self.percent())
+			.with("used", util::human_bytes(self.used()))
+			.with("free", util::human_bytes(self.free))
+			.with("available", util::human_bytes(self.available))
+			.with("total", util::human_bytes(self.total))
+	}
+}
+
+/// Reads the stats of filesystems on blocking threads, so that one which hangs doesn't hold up the
+/// rest of the bar. A call which times out is left running, and its mount point isn't read again
+/// until it returns.
+#[derive(Default)]
+struct Reader {
+	pending: HashMap<String, JoinHandle<nix::Result<Statvfs>>>,
+}
+
+impl Reader {
+	async fn read(&mut self, mount: &str) -> Result<Usage, Error> {
+		let error = |source| Error::Read {
+			path: mount.to_string(),
+			source,
+		};
+		let timed_out = || error(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+		let mut handle = match self.pending.remove(mount) {
+			Some(handle) if !handle.is_finished() => {
+				self.pending.insert(mount.to_string(), handle);
+				return Err(timed_out());
+			}
+			Some(handle) => handle,
+			None => {
+				let path = mount.to_string();
+				tokio::task::spawn_blocking(move || statvfs(path.as_str()))
+			}
+		};
+		let Ok(result) = timeout(STATVFS_TIMEOUT, &mut handle).await else {
+			self.pending.insert(mount.to_string(), handle);
+			return Err(timed_out());
+		};
+		let stats = result
+			.map_err(|e| error(io::Error::other(e)))?
+			.map_err(|e| error(io::Error::from(e)))?;
+		Ok(Usage::new(&stats))
+	}
+}
+
+/// The mount points in `mounts`, as in `/proc/self/mounts`, of filesystems on block devices,
+/// skipping virtual filesystems and mounts of a device already seen, such as bind mounts.
+fn real_mounts(mounts: &str) -> Vec<String> {
+	mounts
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			Some((fields.next()?, unescape(fields.next()?)))
+		})
+		.filter(|(device, _)| device.starts_with("/dev/"))
+		.unique_by(|(device, _)| *device)
+		.map(|(_, mount)| mount)
+		.collect()
+}
+
+/// Undo the octal escapes the kernel uses for spaces, tabs, newlines and backslashes in paths.
+fn unescape(path: &str) -> String {
+	path.replace("\\040", " ")
+		.replace("\\011", "\t")
+		.replace("\\012", "\n")
+		.replace("\\134", "\\")
+}
+
+impl IntoStream for Disk {
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let period = Duration::from_millis(self.period);
+		let mut reader = Reader::default();
+		Ok(try_stream! {
+			loop {
+				// Mounts come and go, e.g. USB drives, so are looked for on every update
+				let mounts = match self.mount_points.is_empty() {
+					true => real_mounts(&util::read_to_ty::<_, String>(&self.mounts_path).await?),
+					false => self.mount_points.clone(),
+				};
+				// A mount point which can't be read, e.g. a missing USB drive or a hung network
+				// filesystem, shows its error in its place rather than failing the whole block
+				let mut texts = Vec::new();
+				let mut percents = Vec::new();
+				for mount in &mounts {
+					match reader.read(mount).await {
+						Ok(usage) => {
+							texts.push(self.format.render(&usage.values(mount)));
+							percents.push(usage.percent());
+						}
+						Err(e) => {
+							tracing::warn!(error = %e, "failed to read filesystem stats");
+							texts.push(e.to_string());
+						}
+					}
+				}
+				let mut update = Update::new(texts.join(&self.join_with));
+				if let Some(percent) = percents.into_iter().reduce(f32::max) {
+					update = update.with_value(percent);
+				}
+				yield update;
+				pause::sleep(period).await;
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures_util::{pin_mut, StreamExt};
+
+	#[test]
+	fn discover_mounts() {
+		let mounts = "proc /proc proc rw,relatime 0 0
+/dev/sda2 / ext4 rw,relatime 0 0
+tmpfs /tmp tmpfs rw 0 0
+/dev/sda1 /boot/efi vfat rw 0 0
+/dev/sdb1 /media/usb\\040drive exfat rw 0 0
+/dev/sda2 /var/lib/docker ext4 rw,relatime 0 0
+";
+		assert_eq!(
+			real_mounts(mounts),
+			vec!["/", "/boot/efi", "/media/usb drive"]
+		);
+	}
+
+	#[test]
+	fn configuration() {
+		let string = "
+			[Disk]
+			mount_points = ['/', '/home']
+			join_with = ' | '
+			separator = false
+		";
+		let mut blocks = crate::config::deserialise(string).unwrap().blocks;
+		let config = blocks.remove(0);
+		assert_eq!(config.attributes.separator, Some(false));
+		match config.block {
+			crate::blocks::Block::Disk(disk) => assert_eq!(disk.join_with, " | "),
+			_ => panic!(),
+		}
+	}
+
+	#[tokio::test]
+	async fn usage() {
+		let usage = Usage {
+			total: 100.0,
+			free: 40.0,
+			available: 20.0,
+		};
+		assert_eq!(usage.used(), 60.0);
+		assert_eq!(usage.percent(), 75.0);
+
+		let mut reader = Reader::default();
+		let root = reader.read("/").await.unwrap();
+		assert!(root.total > 0.0 && root.free <= root.total);
+		assert!(reader.read("/nonexistent").await.is_err());
+		assert!(reader.pending.is_empty());
+	}
+
+	#[tokio::test]
+	async fn unreadable_mount() {
+		let disk: Disk =
+			toml::from_str("mount_points = ['/nonexistent', '/']\nformat = '{mount}'").unwrap();
+		let stream = disk.try_into_stream().await.unwrap();
+		pin_mut!(stream);
+		let update = stream.next().await.unwrap().unwrap().into();
+		assert_eq!(
+			update.full_text,
+			"failed to read '/nonexistent': No such file or directory (os error 2) /"
+		);
+		assert!(update.value.is_some());
+	}
+}
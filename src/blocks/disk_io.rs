@@ -0,0 +1,334 @@
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{default_alpha, default_period, pause, prelude::*, util, Update};
+use crate::theme;
+use crate::Error;
+use async_stream::try_stream;
+use futures_util::Stream;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use rs_blocks_macros::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::time::{Duration, Instant};
+
+/// The values available to `format`, where `icon` is the theme's `disk` icon, `read` and `write`
+/// are in kB/s, `iops` counts reads and writes a second and `busy` is the percentage of the time
+/// the device had I/O in flight. `device` is missing when devices are aggregated.
+const VARIABLES: &[&str] = &["icon", "device", "read", "write", "iops", "busy"];
+
+/// Where the kernel lists whole disks, as opposed to their partitions.
+const SYS_BLOCK: &str = "/sys/block";
+
+/// Prefixes of virtual devices, whose I/O is either not to a disk or already counted by the disks
+/// beneath them.
+const VIRTUAL: &[&str] = &["loop", "ram", "zram", "dm-", "md"];
+
+/// Read and write throughput of disks. The block's value is the highest `busy` shown.
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
+pub struct DiskIo {
+	/// The devices to show, e.g. `nvme0n1`, or every whole disk if empty.
+	#[serde(default)]
+	devices: Vec<String>,
+	/// Whether the devices are shown as one, with their rates added up, or each with `format`.
+	#[serde(default = "default_aggregate")]
+	aggregate: bool,
+	#[serde(default = "default_diskstats_path")]
+	diskstats_path: String,
+	/// What goes between devices which aren't aggregated. This can't be called `separator`, which
+	/// is the i3bar attribute.
+	#[serde(default = "default_join_with")]
+	join_with: String,
+}
+
+fn default_aggregate() -> bool {
+	true
+}
+
+fn default_diskstats_path() -> String {
+	"/proc/diskstats".to_string()
+}
+
+fn default_join_with() -> String {
+	" ".to_string()
+}
+
+fn default_format() -> Template {
+	"{icon}[ {device}] {read:.0}/{write:.0} kB/s"
+		.parse()
+		.unwrap()
+}
+
+/// A device's cumulative counters from `/proc/diskstats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counters {
+	reads: f64,
+	sectors_read: f64,
+	writes: f64,
+	sectors_written: f64,
+	/// Milliseconds spent with I/O in flight.
+	io_ms: f64,
+}
+
+impl Counters {
+	/// Parse each device's line of `/proc/diskstats`, keeping them in file order.
+	fn parse(contents: &str) -> Result<IndexMap<String, Self>, Error> {
+		contents
+			.lines()
+			.filter(|line| !line.trim().is_empty())
+			.map(|line| {
+				let fields: Vec<&str> = line.split_whitespace().collect();
+				let field = |i: usize| -> Result<f64, Error> {
+					let value = fields.get(i).ok_or_else(|| Error::Parse {
+						ty: std::any::type_name::<Self>(),
+						reason: format!("too few fields in '{}'", line.trim()),
+					})?;
+					value.parse().map_err(|_| Error::Parse {
+						ty: "f64",
+						reason: format!("invalid field '{value}' in '{}'", line.trim()),
+					})
+				};
+				let counters = Self {
+					reads: field(3)?,
+					sectors_read: field(5)?,
+					writes: field(7)?,
+					sectors_written: field(9)?,
+					io_ms: field(12)?,
+				};
+				Ok((fields[2].to_string(), counters))
+			})
+			.collect()
+	}
+
+	/// The rates since `prev`, `elapsed` ago. Sectors are always 512 bytes here, whatever the
+	/// disk's own sector size.
+	fn rates(&self, prev: &Self, elapsed: Duration) -> Rates {
+		let secs = elapsed.as_secs_f64();
+		let per_sec = |curr: f64, prev: f64| ((curr - prev).max(0.0) / secs) as f32;
+		Rates {
+			read: per_sec(self.sectors_read, prev.sectors_read) * 512.0 / 1024.0,
+			write: per_sec(self.sectors_written, prev.sectors_written) * 512.0 / 1024.0,
+			iops: per_sec(self.reads + self.writes, prev.reads + prev.writes),
+			busy: (per_sec(self.io_ms, prev.io_ms) / 10.0).min(100.0),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Rates {
+	read: f32,
+	write: f32,
+	iops: f32,
+	busy: f32,
+}
+
+impl Rates {
+	/// The rates of several devices as one. Throughput adds up, whereas the busiest device is the
+	/// one holding things up.
+	fn combine(rates: impl IntoIterator<Item = Rates>) -> Self {
+		rates.into_iter().fold(Self::default(), |acc, x| Self {
+			read: acc.read + x.read,
+			write: acc.write + x.write,
+			iops: acc.iops + x.iops,
+			busy: acc.busy.max(x.busy),
+		})
+	}
+}
+
+/// Smooths each rate with its own `Ema`.
+struct Smoothed {
+	read: util::Ema<f32>,
+	write: util::Ema<f32>,
+	iops: util::Ema<f32>,
+	busy: util::Ema<f32>,
+}
+
+impl Smoothed {
+	fn new(alpha: f32) -> Self {
+		Self {
+			read: util::Ema::new(alpha),
+			write: util::Ema::new(alpha),
+			iops: util::Ema::new(alpha),
+			busy: util::Ema::new(alpha),
+		}
+	}
+
+	fn push(&mut self, rates: Rates) -> Rates {
+		Rates {
+			read: self.read.push(rates.read),
+			write: self.write.push(rates.write),
+			iops: self.iops.push(rates.iops),
+			busy: self.busy.push(rates.busy),
+		}
+	}
+}
+
+/// Whether `name` is a whole disk rather than a partition or a virtual device.
+fn is_disk(name: &str) -> bool {
+	!VIRTUAL.iter().any(|x| name.starts_with(x)) && Path::new(SYS_BLOCK).join(name).exists()
+}
+
+impl DiskIo {
+	/// The devices to show out of those in `counters`.
+	fn select(&self, counters: &IndexMap<String, Counters>) -> Result<Vec<String>, Error> {
+		if self.devices.is_empty() {
+			return Ok(counters.keys().filter(|x| is_disk(x)).cloned().collect());
+		}
+		match self.devices.iter().find(|x| !counters.contains_key(*x)) {
+			Some(missing) => Err(Error::NoDevice(missing.clone())),
+			None => Ok(self.devices.clone()),
+		}
+	}
+
+	fn render(&self, device: Option<&str>, rates: Rates) -> String {
+		let values = Values::new()
+			.with("icon", theme::icon("disk"))
+			.with("device", device)
+			.with("read", rates.read)
+			.with("write", rates.write)
+			.with("iops", rates.iops)
+			.with("busy", rates.busy);
+		self.format.render(&values)
+	}
+}
+
+impl IntoStream for DiskIo {
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let period = Duration::from_millis(self.period);
+		let mut smoothed: HashMap<String, Smoothed> = HashMap::new();
+		Ok(try_stream! {
+			let read = || util::read_to_ty::<_, String>(&self.diskstats_path);
+			let mut prev = Counters::parse(&read().await?)?;
+			let mut then = Instant::now();
+			loop {
+				// As for `Network`, rates are over the time which actually passed
+				pause::sleep(period).await;
+				let elapsed = then.elapsed();
+				then = Instant::now();
+				let counters = Counters::parse(&read().await?)?;
+				let devices = self.select(&counters)?;
+				// A device which has only just appeared has no rates yet
+				let rates: Vec<(&str, Rates)> = devices
+					.iter()
+					.filter_map(|x| {
+						let rates = counters.get(x)?.rates(prev.get(x)?, elapsed);
+						Some((x.as_str(), rates))
+					})
+					.collect();
+				let mut smooth = |device: &str, rates| {
+					smoothed
+						.entry(device.to_string())
+						.or_insert_with(|| Smoothed::new(self.alpha))
+						.push(rates)
+				};
+				let (text, busy) = if self.aggregate {
+					let rates = smooth("", Rates::combine(rates.into_iter().map(|x| x.1)));
+					(self.render(None, rates), rates.busy)
+				} else {
+					let rates: Vec<_> = rates
+						.into_iter()
+						.map(|(device, rates)| (device, smooth(device, rates)))
+						.collect();
+					let text = rates
+						.iter()
+						.map(|(device, rates)| self.render(Some(device), *rates))
+						.join(&self.join_with);
+					(text, rates.iter().map(|x| x.1.busy).fold(0.0, f32::max))
+				};
+				yield Update::new(text).with_value(busy);
+				prev = counters;
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const DISKSTATS: &str = "   7       0 loop0 10 0 80 0 0 0 0 0 0 4 4 0 0 0 0 0 0
+ 259       0 nvme0n1 1000 0 20000 500 2000 0 40000 900 0 1000 1400 0 0 0 0 0 0
+ 259       1 nvme0n1p1 900 0 18000 450 1900 0 38000 850 0 950 1300 0 0 0 0 0 0
+";
+
+	#[test]
+	fn parse_diskstats() {
+		let counters = Counters::parse(DISKSTATS).unwrap();
+		assert_eq!(
+			counters.keys().collect::<Vec<_>>(),
+			vec!["loop0", "nvme0n1", "nvme0n1p1"]
+		);
+		assert_eq!(
+			counters["nvme0n1"],
+			Counters {
+				reads: 1000.0,
+				sectors_read: 20000.0,
+				writes: 2000.0,
+				sectors_written: 40000.0,
+				io_ms: 1000.0,
+			}
+		);
+		assert!(Counters::parse("259 0 nvme0n1 1 2").is_err());
+		assert!(!is_disk("loop0"));
+	}
+
+	#[test]
+	fn configuration() {
+		let string = "
+			[DiskIo]
+			aggregate = false
+			join_with = ' | '
+			separator = false
+		";
+		let mut blocks = crate::config::deserialise(string).unwrap().blocks;
+		let config = blocks.remove(0);
+		assert_eq!(config.attributes.separator, Some(false));
+		match config.block {
+			crate::blocks::Block::DiskIo(disk_io) => assert_eq!(disk_io.join_with, " | "),
+			_ => panic!(),
+		}
+	}
+
+	#[test]
+	fn rates() {
+		let prev = Counters::default();
+		let curr = Counters {
+			reads: 10.0,
+			sectors_read: 2048.0,
+			writes: 30.0,
+			sectors_written: 4096.0,
+			io_ms: 500.0,
+		};
+		let rates = curr.rates(&prev, Duration::from_secs(2));
+		assert_eq!(
+			rates,
+			Rates {
+				read: 512.0,
+				write: 1024.0,
+				iops: 20.0,
+				busy: 25.0,
+			}
+		);
+		let other = Rates {
+			busy: 50.0,
+			..rates
+		};
+		assert_eq!(
+			Rates::combine([rates, other]),
+			Rates {
+				read: 1024.0,
+				write: 2048.0,
+				iops: 40.0,
+				busy: 50.0,
+			}
+		);
+	}
+}
@@ -0,0 +1,338 @@
+use crate::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A value a block exposes to its format template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+	/// The value isn't available, e.g. the time remaining while it's still being estimated.
+	Missing,
+	Number(f64),
+	Text(String),
+}
+
+impl From<f32> for Value {
+	fn from(value: f32) -> Self {
+		Self::Number(value.into())
+	}
+}
+
+impl From<f64> for Value {
+	fn from(value: f64) -> Self {
+		Self::Number(value)
+	}
+}
+
+impl From<u32> for Value {
+	fn from(value: u32) -> Self {
+		Self::Number(value.into())
+	}
+}
+
+impl From<String> for Value {
+	fn from(value: String) -> Self {
+		Self::Text(value)
+	}
+}
+
+impl From<&str> for Value {
+	fn from(value: &str) -> Self {
+		Self::Text(value.to_string())
+	}
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+	fn from(value: Option<T>) -> Self {
+		value.map_or(Self::Missing, Into::into)
+	}
+}
+
+/// The values of a template's placeholders.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Values(HashMap<&'static str, Value>);
+
+impl Values {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+		self.0.insert(name, value.into());
+		self
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+	Left,
+	Right,
+	Center,
+}
+
+/// How a placeholder's value is formatted, following the `[align][width][.precision]` subset of
+/// Rust's format spec.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Spec {
+	align: Option<Align>,
+	width: usize,
+	precision: Option<usize>,
+}
+
+impl Spec {
+	fn parse(spec: &str) -> Result<Self, String> {
+		let invalid = || format!("invalid format spec '{spec}'");
+		let (align, rest) = match spec.chars().next() {
+			Some('<') => (Some(Align::Left), &spec[1..]),
+			Some('>') => (Some(Align::Right), &spec[1..]),
+			Some('^') => (Some(Align::Center), &spec[1..]),
+			_ => (None, spec),
+		};
+		let (width, precision) = match rest.split_once('.') {
+			Some((width, precision)) => (width, Some(precision.parse().map_err(|_| invalid())?)),
+			None => (rest, None),
+		};
+		let width = match width {
+			"" => 0,
+			width => width.parse().map_err(|_| invalid())?,
+		};
+		Ok(Self {
+			align,
+			width,
+			precision,
+		})
+	}
+
+	fn apply(&self, value: &Value) -> String {
+		let text = match (value, self.precision) {
+			(Value::Number(x), Some(precision)) => format!("{x:.precision$}"),
+			(Value::Number(x), None) => x.to_string(),
+			(Value::Text(x), Some(precision)) => x.chars().take(precision).collect(),
+			(Value::Text(x), None) => x.clone(),
+			(Value::Missing, _) => String::new(),
+		};
+		let width = self.width;
+		// Numbers are right aligned by default and text left aligned, as with `format!`
+		match (self.align, value) {
+			(Some(Align::Right), _) | (None, Value::Number(_)) => format!("{text:>width$}"),
+			(Some(Align::Center), _) => format!("{text:^width$}"),
+			_ => format!("{text:<width$}"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+	Text(String),
+	Placeholder {
+		name: String,
+		spec: Spec,
+	},
+	/// Only shown when every placeholder within it has a value.
+	Section(Vec<Part>),
+}
+
+/// A format string with named placeholders, e.g. `"{icon} {percent:.0}%"`.
+///
+/// Placeholders take an optional spec after a colon, made of an alignment (`<`, `>` or `^`), a
+/// width and a precision, e.g. `{rx:>6.1}`. Text within square brackets is a section, which is
+/// only shown when every placeholder within it has a value, e.g. `"{percent}%[ ({remaining})]"`.
+/// A literal brace is written `{{` or `}}` and a literal square bracket `\[` or `\]`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Template(Vec<Part>);
+
+impl TryFrom<String> for Template {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl std::str::FromStr for Template {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut chars = s.chars().peekable();
+		// The parts of each section currently open, the template itself being the outermost
+		let mut stack = vec![Vec::new()];
+		let mut text = String::new();
+		while let Some(c) = chars.next() {
+			match c {
+				'{' if chars.peek() == Some(&'{') => {
+					chars.next();
+					text.push('{');
+				}
+				'}' if chars.peek() == Some(&'}') => {
+					chars.next();
+					text.push('}');
+				}
+				'\\' if matches!(chars.peek(), Some('[' | ']')) => text.extend(chars.next()),
+				'{' => {
+					let mut placeholder = String::new();
+					loop {
+						match chars.next() {
+							Some('}') => break,
+							Some(c) => placeholder.push(c),
+							None => return Err(format!("unclosed placeholder in '{s}'")),
+						}
+					}
+					let (name, spec) = match placeholder.split_once(':') {
+						Some((name, spec)) => (name, Spec::parse(spec)?),
+						None => (placeholder.as_str(), Spec::default()),
+					};
+					if name.is_empty() {
+						return Err(format!("empty placeholder in '{s}'"));
+					}
+					let parts = stack.last_mut().unwrap();
+					parts.extend(text_part(&mut text));
+					parts.push(Part::Placeholder {
+						name: name.to_string(),
+						spec,
+					});
+				}
+				'}' => return Err(format!("unmatched '}}' in '{s}'")),
+				'[' => {
+					stack.last_mut().unwrap().extend(text_part(&mut text));
+					stack.push(Vec::new());
+				}
+				']' => {
+					if stack.len() == 1 {
+						return Err(format!("unmatched ']' in '{s}'"));
+					}
+					let mut section = stack.pop().unwrap();
+					section.extend(text_part(&mut text));
+					stack.last_mut().unwrap().push(Part::Section(section));
+				}
+				c => text.push(c),
+			}
+		}
+		if stack.len() > 1 {
+			return Err(format!("unclosed section in '{s}'"));
+		}
+		let mut parts = stack.pop().unwrap();
+		parts.extend(text_part(&mut text));
+		Ok(Self(parts))
+	}
+}
+
+/// Take the text accumulated so far as a part, if there is any.
+fn text_part(text: &mut String) -> Option<Part> {
+	if text.is_empty() {
+		None
+	} else {
+		Some(Part::Text(std::mem::take(text)))
+	}
+}
+
+/// Render `parts`, also returning whether every placeholder in them had a value.
+fn render(parts: &[Part], values: &Values) -> (String, bool) {
+	let mut output = String::new();
+	let mut complete = true;
+	for part in parts {
+		match part {
+			Part::Text(text) => output.push_str(text),
+			Part::Placeholder { name, spec } => {
+				let value = values.0.get(name.as_str()).unwrap_or(&Value::Missing);
+				complete &= *value != Value::Missing;
+				output.push_str(&spec.apply(value));
+			}
+			Part::Section(parts) => {
+				if let (text, true) = render(parts, values) {
+					output.push_str(&text);
+				}
+			}
+		}
+	}
+	(output, complete)
+}
+
+impl Template {
+	/// Fill in the placeholders with `values`. Placeholders without a value are left empty.
+	pub fn render(&self, values: &Values) -> String {
+		render(&self.0, values).0
+	}
+
+	/// Check that every placeholder is one of `names`, the values a block provides.
+	pub fn check(&self, names: &[&str]) -> Result<(), Error> {
+		fn check(parts: &[Part], names: &[&str]) -> Result<(), Error> {
+			for part in parts {
+				match part {
+					Part::Placeholder { name, .. } if !names.contains(&name.as_str()) => {
+						return Err(Error::Parse {
+							ty: "Template",
+							reason: format!(
+								"unknown placeholder '{{{name}}}', expected one of: {}",
+								names.join(", ")
+							),
+						});
+					}
+					Part::Section(parts) => check(parts, names)?,
+					_ => {}
+				}
+			}
+			Ok(())
+		}
+		check(&self.0, names)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn render(template: &str, values: &Values) -> String {
+		template.parse::<Template>().unwrap().render(values)
+	}
+
+	#[test]
+	fn placeholders() {
+		let values = Values::new()
+			.with("percent", 53.612)
+			.with("icon", "")
+			.with("name", "ab")
+			.with("level", 7u32);
+		assert_eq!(render("{icon} {percent:.1}%", &values), " 53.6%");
+		assert_eq!(render("{percent:.0}", &values), "54");
+		assert_eq!(render("{level}", &values), "7");
+		assert_eq!(
+			render("{level:3}|{name:3}|{name:>3}|{name:^4}|{name:.1}", &values),
+			"  7|ab | ab| ab |a"
+		);
+		assert_eq!(render("{{{level}}}", &values), "{7}");
+		assert_eq!(render(r"\[{level}\]", &values), "[7]");
+	}
+
+	#[test]
+	fn sections() {
+		let values = Values::new()
+			.with("percent", 80.0)
+			.with("remaining", None::<String>);
+		assert_eq!(render("{percent}%[ ({remaining})]", &values), "80%");
+		let values = values.with("remaining", Some("1h05m"));
+		assert_eq!(render("{percent}%[ ({remaining})]", &values), "80% (1h05m)");
+		assert_eq!(render("[a[{missing}]b]", &values), "ab");
+		assert_eq!(render("x{missing}y", &values), "xy");
+	}
+
+	#[test]
+	fn invalid_templates() {
+		for template in [
+			"{percent",
+			"percent}",
+			"[{percent}",
+			"{percent}]",
+			"{}",
+			"{a:x}",
+		] {
+			assert!(template.parse::<Template>().is_err(), "{template}");
+		}
+	}
+
+	#[test]
+	fn unknown_placeholders() {
+		let template: Template = "{percent} [{remaining}]".parse().unwrap();
+		assert!(template.check(&["percent", "remaining"]).is_ok());
+		assert!(template.check(&["percent"]).is_err());
+	}
+}
@@ -1,48 +1,238 @@
-use crate::blocks::{default_alpha, default_period, prelude::*, util, StreamExt2};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{default_alpha, default_period, prelude::*, util, Update};
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
 use futures_util::Stream;
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use tokio::time::{sleep, Duration};
+use std::collections::HashMap;
+use tokio::time::Duration;
 
-const PATTERN: &str = r"(?s)MemTotal:\s+(?<total>\d+).+MemFree:\s+(?<free>\d+)";
+/// The values available to `format`, where `icon` is the theme's `memory` icon, `percent` is the
+/// usage chosen by `mode` and the rest are absolute amounts in human units.
+const VARIABLES: &[&str] = &[
+	"icon",
+	"percent",
+	"used_percent",
+	"swap_percent",
+	"cache_percent",
+	"total",
+	"used",
+	"available",
+	"free",
+	"buffers",
+	"cached",
+	"swap_total",
+	"swap_used",
+	"swap_free",
+];
+const KEYS: &[&str] = &[
+	"MemTotal",
+	"MemFree",
+	"MemAvailable",
+	"Buffers",
+	"Cached",
+	"SReclaimable",
+	"SwapTotal",
+	"SwapFree",
+];
 
-#[with_fields(alpha, period)]
-#[derive(Debug, Deserialize, NoMarkup, GetName, IntoSerialized)]
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
 pub struct Memory {
 	#[serde(default = "default_meminfo_path")]
 	meminfo_path: String,
+	#[serde(default)]
+	mode: Mode,
+}
+
+/// The usage shown as `percent`, which is also the block's value for thresholds.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+	/// Memory which isn't available to start programs without swapping.
+	#[default]
+	Used,
+	/// Swap in use.
+	Swap,
+	/// Buffers and the page cache, which the kernel frees as programs need the memory.
+	Cache,
 }
 
 fn default_meminfo_path() -> String {
 	"/proc/meminfo".to_string()
 }
 
-#[derive(TryFromCaptures)]
+fn default_format() -> Template {
+	"{icon} {percent:.1}%".parse().unwrap()
+}
+
+/// The contents of `/proc/meminfo`, in kB.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 struct MemStats {
-	total: f32,
-	free: f32,
+	total: f64,
+	free: f64,
+	available: f64,
+	buffers: f64,
+	/// The page cache and reclaimable slab, as counted by `free`.
+	cached: f64,
+	swap_total: f64,
+	swap_free: f64,
 }
 
 impl MemStats {
-	fn percent(&self) -> f32 {
-		100.0 * (1.0 - self.free / self.total)
+	/// Parse the lines of `/proc/meminfo`. Only `MemTotal` is required, and `MemAvailable` is
+	/// estimated from the free and cached memory on kernels too old to have it.
+	fn parse(contents: &str) -> Result<Self, Error> {
+		let values: HashMap<&str, f64> = contents
+			.lines()
+			.filter_map(|line| {
+				let (key, rest) = line.split_once(':')?;
+				let value = rest.split_whitespace().next()?.parse().ok()?;
+				Some((key, value))
+			})
+			.collect();
+		let get = |key| values.get(key).copied().unwrap_or_default();
+		let total = values
+			.get("MemTotal")
+			.copied()
+			.ok_or_else(|| Error::Parse {
+				ty: std::any::type_name::<Self>(),
+				reason: "missing MemTotal".to_string(),
+			})?;
+		let free = get("MemFree");
+		let buffers = get("Buffers");
+		let cached = get("Cached") + get("SReclaimable");
+		Ok(Self {
+			total,
+			free,
+			available: values
+				.get("MemAvailable")
+				.copied()
+				.unwrap_or(free + buffers + cached),
+			buffers,
+			cached,
+			swap_total: get("SwapTotal"),
+			swap_free: get("SwapFree"),
+		})
+	}
+
+	fn used(&self) -> f64 {
+		self.total - self.available
+	}
+
+	fn swap_used(&self) -> f64 {
+		self.swap_total - self.swap_free
+	}
+
+	fn used_percent(&self) -> f32 {
+		percent(self.used(), self.total)
+	}
+
+	fn swap_percent(&self) -> f32 {
+		percent(self.swap_used(), self.swap_total)
+	}
+
+	fn cache_percent(&self) -> f32 {
+		percent(self.buffers + self.cached, self.total)
+	}
+
+	fn percent(&self, mode: Mode) -> f32 {
+		match mode {
+			Mode::Used => self.used_percent(),
+			Mode::Swap => self.swap_percent(),
+			Mode::Cache => self.cache_percent(),
+		}
+	}
+
+	/// The values for `format`, with `percent` given separately as it's smoothed.
+	fn values(&self, percent: f32) -> Values {
+		let human = |kb: f64| util::human_bytes(kb * 1024.0);
+		Values::new()
+			.with("icon", theme::icon("memory"))
+			.with("percent", percent)
+			.with("used_percent", self.used_percent())
+			.with("swap_percent", self.swap_percent())
+			.with("cache_percent", self.cache_percent())
+			.with("total", human(self.total))
+			.with("used", human(self.used()))
+			.with("available", human(self.available))
+			.with("free", human(self.free))
+			.with("buffers", human(self.buffers))
+			.with("cached", human(self.cached))
+			.with("swap_total", human(self.swap_total))
+			.with("swap_used", human(self.swap_used()))
+			.with("swap_free", human(self.swap_free))
+	}
+}
+
+/// `part` as a percentage of `whole`, which is 0 when there's no whole, e.g. without swap.
+fn percent(part: f64, whole: f64) -> f32 {
+	match whole > 0.0 {
+		true => (100.0 * part / whole) as f32,
+		false => 0.0,
 	}
 }
 
 impl IntoStream for Memory {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let re = regex::Regex::new(PATTERN).unwrap();
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
 		let mut ema = util::Ema::new(self.alpha);
-		try_stream! {
-			let watcher = util::watch::<_, 100>(&self.meminfo_path)
-				.with_period(|| sleep(Duration::from_millis(self.period)));
+		Ok(try_stream! {
+			let period = Duration::from_millis(self.period);
+			let watcher = util::watch_keys(&self.meminfo_path, period, KEYS);
 			for await contents in watcher {
-				let stats: MemStats = util::from_string(&re, &contents?)?;
-				ema.push(stats.percent());
-				yield format!(" {:.1}%", ema);
+				let stats = MemStats::parse(&contents?)?;
+				let percent = ema.push(stats.percent(self.mode));
+				let text = self.format.render(&stats.values(percent));
+				yield Update::new(text).with_value(percent);
 			}
-		}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const MEMINFO: &str = "MemTotal:       16000000 kB
+MemFree:         1000000 kB
+MemAvailable:    8000000 kB
+Buffers:          500000 kB
+Cached:          5000000 kB
+SwapCached:            0 kB
+SReclaimable:     500000 kB
+SwapTotal:       2000000 kB
+SwapFree:        1500000 kB
+";
+
+	#[test]
+	fn parse_meminfo() {
+		let stats = MemStats::parse(MEMINFO).unwrap();
+		assert_eq!(stats.cached, 5_500_000.0);
+		assert_eq!(stats.percent(Mode::Used), 50.0);
+		assert_eq!(stats.percent(Mode::Swap), 25.0);
+		assert_eq!(stats.percent(Mode::Cache), 37.5);
+
+		let old = MemStats::parse("MemTotal: 1000 kB\nMemFree: 100 kB\nCached: 400 kB").unwrap();
+		assert_eq!(old.available, 500.0);
+		assert_eq!(old.swap_percent(), 0.0);
+		assert!(MemStats::parse("MemFree: 100 kB").is_err());
+	}
+
+	#[test]
+	fn human_values() {
+		let stats = MemStats::parse(MEMINFO).unwrap();
+		let format: Template = "{used}/{total} swap {swap_used} {percent:.0}%"
+			.parse()
+			.unwrap();
+		assert_eq!(format.render(&stats.values(50.0)), "7.6G/15G swap 488M 50%");
 	}
 }
@@ -1,30 +1,48 @@
+use crate::click::ClickEvent;
+use crate::theme;
 use crate::Error;
-use async_stream::try_stream;
-use futures_util::Stream;
-use serde::Serialize;
+use async_stream::{stream, try_stream};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
 use std::pin::Pin;
 
 pub mod battery;
 pub mod brightness;
+pub mod command;
 pub mod cpu;
+pub mod disk;
+pub mod disk_io;
+pub mod format;
 pub mod memory;
 pub mod network;
+pub mod notify;
+pub mod pause;
 pub mod stream_ext;
+pub mod temperature;
+pub mod thresholds;
 pub mod time;
 pub mod util;
 pub mod volume;
 
 pub use battery::Battery;
 pub use brightness::Brightness;
+pub use command::Command;
 pub use cpu::Cpu;
+pub use disk::Disk;
+pub use disk_io::DiskIo;
 pub use memory::Memory;
 pub use network::Network;
 pub use stream_ext::StreamExt2;
+pub use temperature::Temperature;
+pub use thresholds::{State, Thresholds};
 pub use time::Time;
 pub use volume::Volume;
 
 pub mod prelude {
-	pub use super::{GetMarkup, GetName, IntoSerialized, IntoStream};
+	pub use super::{GetMarkup, GetName, IntoSerialized, IntoStream, OnClick};
 }
 
 pub trait GetName {
@@ -37,74 +55,327 @@ pub trait GetMarkup {
 	}
 }
 
-/// Struct that will be serialised to produce a block. Note that there are many other attributes
-/// we could introduce here, but these are the only ones being used at the moment.
-#[derive(Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+	Left,
+	Center,
+	Right,
+}
+
+/// The minimum width of a block, either in pixels or as the width of some text.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MinWidth {
+	Pixels(u32),
+	Text(String),
+}
+
+/// The optional attributes of an i3bar block. These can be set per block in the config and by the
+/// block itself on each update.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Attributes {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub short_text: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub color: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub background: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub border: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub border_top: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub border_right: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub border_bottom: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub border_left: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub min_width: Option<MinWidth>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub align: Option<Align>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub urgent: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub instance: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub separator: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub separator_block_width: Option<u32>,
+}
+
+impl Attributes {
+	/// Fill any attributes which are unset in `self` from `defaults`.
+	pub fn or(self, defaults: &Attributes) -> Attributes {
+		macro_rules! or {
+			($($field:ident),*) => {
+				Attributes {
+					$($field: self.$field.or_else(|| defaults.$field.clone()),)*
+				}
+			};
+		}
+		or!(
+			short_text,
+			color,
+			background,
+			border,
+			border_top,
+			border_right,
+			border_bottom,
+			border_left,
+			min_width,
+			align,
+			urgent,
+			instance,
+			separator,
+			separator_block_width
+		)
+	}
+}
+
+/// A single update yielded by a block.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Update {
+	pub full_text: String,
+	pub attributes: Attributes,
+	/// The value shown by the block, e.g. a percentage, which is compared against its thresholds.
+	pub value: Option<f64>,
+	/// A state the block is in regardless of its value, e.g. good while a battery charges.
+	pub state: Option<State>,
+}
+
+impl Update {
+	pub fn new(full_text: String) -> Self {
+		Self {
+			full_text,
+			attributes: Attributes::default(),
+			value: None,
+			state: None,
+		}
+	}
+
+	pub fn with_value(self, value: impl Into<f64>) -> Self {
+		Self {
+			value: Some(value.into()),
+			..self
+		}
+	}
+
+	pub fn with_state(self, state: State) -> Self {
+		Self {
+			state: Some(state),
+			..self
+		}
+	}
+}
+
+impl From<String> for Update {
+	fn from(full_text: String) -> Self {
+		Self::new(full_text)
+	}
+}
+
+/// Struct that will be serialised to produce a block. How it's serialised depends on the output,
+/// see `output::Output`; the i3bar protocol uses the serde implementation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Serialized {
 	pub name: &'static str,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	pub full_text: Option<String>,
+	pub full_text: String,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub markup: Option<&'static str>,
+	#[serde(flatten)]
+	pub attributes: Attributes,
+	/// The block's value, which i3bar has no field for.
+	#[serde(skip)]
+	pub value: Option<f64>,
+	/// The block's state after its thresholds, which i3bar has no field for.
+	#[serde(skip)]
+	pub state: State,
 }
 
 pub trait IntoSerialized: GetName + GetMarkup {
-	fn into_serialized(full_text: impl Into<Option<String>>) -> Result<String, Error> {
-		let serialized = Serialized {
+	/// Prepare an update for output, using `defaults` for any attributes the update doesn't set
+	/// itself.
+	fn into_serialized(update: Update, defaults: &Attributes) -> Serialized {
+		Serialized {
 			name: Self::get_name(),
-			full_text: full_text.into(),
+			full_text: update.full_text,
 			markup: Self::get_markup(),
-		};
-		serde_json::to_string(&serialized).map_err(Error::Serialize)
+			attributes: update.attributes.or(defaults),
+			value: update.value,
+			state: update.state.unwrap_or(State::Idle),
+		}
+	}
+}
+
+/// React to click events on a block.
+pub trait OnClick {
+	/// Handle a click on the block. If an update is returned it replaces the block's output
+	/// straight away, rather than waiting for the block's stream to next yield.
+	fn on_click(&self, _event: &ClickEvent) -> impl Future<Output = Result<Option<Update>, Error>> {
+		async { Ok(None) }
+	}
+}
+
+/// Identifies a block on the bar. The instance tells apart several blocks of the same type.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BlockId {
+	pub name: String,
+	pub instance: Option<String>,
+}
+
+impl fmt::Display for BlockId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.instance {
+			Some(instance) => write!(f, "{}/{}", self.name, instance),
+			None => write!(f, "{}", self.name),
+		}
 	}
 }
 
 pub struct BlockResult {
-	pub block_name: String,
-	pub text: String,
+	pub id: BlockId,
+	pub block: Serialized,
+}
+
+impl BlockResult {
+	/// Produce a result showing `error` in place of a block's output.
+	pub fn error(name: &'static str, error: &Error, attributes: &Attributes) -> Self {
+		let id = BlockId {
+			name: name.to_string(),
+			instance: attributes.instance.clone(),
+		};
+		let block = Serialized {
+			name,
+			full_text: error.to_string(),
+			markup: None,
+			attributes: Attributes {
+				urgent: Some(true),
+				..attributes.clone()
+			},
+			value: None,
+			state: State::Critical,
+		};
+		Self { id, block }
+	}
 }
 
 pub trait IntoStream {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>>;
+	/// Check the block's config for problems which deserialising it doesn't catch, such as unknown
+	/// placeholders in `format`. This is done on setup and by `--check`.
+	fn check(&self) -> Result<(), Error> {
+		Ok(())
+	}
+
+	/// Set up the block, returning the stream of its updates. Any error during setup is reported
+	/// in the block's place on the bar.
+	fn try_into_stream(
+		self,
+	) -> impl Future<Output = Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error>>;
 
-	fn into_stream_pin(self) -> Pin<Box<dyn Stream<Item = Result<BlockResult, Error>>>>
+	fn into_stream_pin(
+		self,
+		attributes: Attributes,
+		thresholds: Thresholds,
+		clicks: UnboundedReceiver<ClickEvent>,
+	) -> Pin<Box<dyn Stream<Item = Result<BlockResult, Error>>>>
 	where
-		Self: 'static + GetName + IntoSerialized + Sized,
+		Self: 'static + Clone + GetName + IntoSerialized + OnClick + Sized,
 	{
+		let handler = self.clone();
+		let clicks = stream! {
+			for await event in clicks {
+				if let Some(result) = handler.on_click(&event).await.transpose() {
+					yield result;
+				}
+			}
+		};
 		Box::pin(try_stream! {
-			for await result in self.into_stream() {
-				let block_name = Self::get_name().to_string();
-				let full_text = match result {
-					Ok(full_text) => full_text,
-					Err(e) => e.to_string(),
+			let updates = self.try_into_stream().await.map_err(|e| Error::Setup {
+				name: Self::get_name(),
+				reason: e.to_string(),
+			})?;
+			let updates = updates.map(|result| result.map(Into::into));
+			let blocks = futures_util::stream::select(updates, clicks)
+				.map(|result: Result<Update, Error>| -> Result<Serialized, Error> {
+					let mut update = result?;
+					// Colours configured for the block take precedence over its thresholds'
+					update.attributes = update.attributes.or(&attributes);
+					thresholds.apply(&mut update, &theme::current().palette);
+					Ok(Self::into_serialized(update, &attributes))
+				})
+				// Only what's shown on the bar counts as a change, not the value behind it
+				.on_changes(|x: &Serialized| (x.full_text.clone(), x.attributes.clone(), x.state));
+			for await block in blocks {
+				let id = BlockId {
+					name: Self::get_name().to_string(),
+					instance: attributes.instance.clone(),
 				};
-				yield BlockResult { block_name, text: Self::into_serialized(full_text)? };
+				yield BlockResult { id, block: block? };
 			}
 		})
 	}
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Block {
 	Battery(battery::Battery),
 	Brightness(brightness::Brightness),
+	Command(command::Command),
 	Cpu(cpu::Cpu),
+	Disk(disk::Disk),
+	DiskIo(disk_io::DiskIo),
 	Memory(memory::Memory),
 	Network(network::Network),
+	Temperature(temperature::Temperature),
 	Time(time::Time),
 	Volume(volume::Volume),
 }
 
 impl Block {
-	pub fn into_stream_pin(self) -> Pin<Box<dyn Stream<Item = Result<BlockResult, Error>>>> {
+	pub fn into_stream_pin(
+		self,
+		attributes: Attributes,
+		thresholds: Thresholds,
+		clicks: UnboundedReceiver<ClickEvent>,
+	) -> Pin<Box<dyn Stream<Item = Result<BlockResult, Error>>>> {
+		match self {
+			Block::Battery(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Brightness(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Command(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Cpu(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Disk(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::DiskIo(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Memory(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Network(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Temperature(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Time(x) => x.into_stream_pin(attributes, thresholds, clicks),
+			Block::Volume(x) => x.into_stream_pin(attributes, thresholds, clicks),
+		}
+	}
+
+	pub fn check(&self) -> Result<(), Error> {
+		match self {
+			Block::Battery(x) => x.check(),
+			Block::Brightness(x) => x.check(),
+			Block::Command(x) => x.check(),
+			Block::Cpu(x) => x.check(),
+			Block::Disk(x) => x.check(),
+			Block::DiskIo(x) => x.check(),
+			Block::Memory(x) => x.check(),
+			Block::Network(x) => x.check(),
+			Block::Temperature(x) => x.check(),
+			Block::Time(x) => x.check(),
+			Block::Volume(x) => x.check(),
+		}
+	}
+
+	/// The thresholds a block uses for any which aren't configured.
+	pub fn default_thresholds(&self) -> Thresholds {
 		match self {
-			Block::Battery(x) => x.into_stream_pin(),
-			Block::Brightness(x) => x.into_stream_pin(),
-			Block::Cpu(x) => x.into_stream_pin(),
-			Block::Memory(x) => x.into_stream_pin(),
-			Block::Network(x) => x.into_stream_pin(),
-			Block::Time(x) => x.into_stream_pin(),
-			Block::Volume(x) => x.into_stream_pin(),
+			Block::Battery(_) => battery::default_thresholds(),
+			_ => Thresholds::default(),
 		}
 	}
 
@@ -112,9 +383,13 @@ impl Block {
 		match self {
 			Block::Battery(_) => Battery::get_name(),
 			Block::Brightness(_) => Brightness::get_name(),
+			Block::Command(_) => Command::get_name(),
 			Block::Cpu(_) => Cpu::get_name(),
+			Block::Disk(_) => Disk::get_name(),
+			Block::DiskIo(_) => DiskIo::get_name(),
 			Block::Memory(_) => Memory::get_name(),
 			Block::Network(_) => Network::get_name(),
+			Block::Temperature(_) => Temperature::get_name(),
 			Block::Time(_) => Time::get_name(),
 			Block::Volume(_) => Volume::get_name(),
 		}
@@ -128,3 +403,75 @@ pub fn default_alpha() -> f32 {
 pub fn default_period() -> u64 {
 	700
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn attributes_fall_back_to_defaults() {
+		let defaults = Attributes {
+			color: Some("#ffffff".to_string()),
+			urgent: Some(false),
+			..Default::default()
+		};
+		let attributes = Attributes {
+			color: Some("#ff0000".to_string()),
+			short_text: Some("a".to_string()),
+			..Default::default()
+		};
+		let merged = attributes.or(&defaults);
+		assert_eq!(merged.color.as_deref(), Some("#ff0000"));
+		assert_eq!(merged.short_text.as_deref(), Some("a"));
+		assert_eq!(merged.urgent, Some(false));
+		assert_eq!(merged.background, None);
+	}
+
+	#[test]
+	fn serialize_block() {
+		let mut update = Update::new("text".to_string());
+		update.attributes.align = Some(Align::Center);
+		update.attributes.min_width = Some(MinWidth::Pixels(100));
+		let defaults = Attributes {
+			border_top: Some(2),
+			..Default::default()
+		};
+		assert_eq!(
+			serde_json::to_string(&Time::into_serialized(update, &defaults)).unwrap(),
+			r#"{"name":"Time","full_text":"text","markup":"pango","border_top":2,"min_width":100,"align":"center"}"#
+		);
+	}
+
+	#[tokio::test]
+	async fn configured_colours_override_thresholds() {
+		let memory = Block::Memory(toml::from_str("").unwrap());
+		let attributes = Attributes {
+			color: Some("#123456".to_string()),
+			..Default::default()
+		};
+		let thresholds = Thresholds {
+			warning: Some(0.0),
+			..Default::default()
+		};
+		let (_tx, rx) = futures::channel::mpsc::unbounded();
+		let mut results = memory.into_stream_pin(attributes, thresholds, rx);
+		let block = results.next().await.unwrap().unwrap().block;
+		assert_eq!(block.state, State::Warning);
+		assert_eq!(block.attributes.color.as_deref(), Some("#123456"));
+	}
+
+	#[test]
+	fn error_state() {
+		let error = Error::Parse {
+			ty: "f32",
+			reason: "oops".to_string(),
+		};
+		let result = BlockResult::error("Cpu", &error, &Attributes::default());
+		assert_eq!(result.id.to_string(), "Cpu");
+		assert_eq!(result.block.state, State::Critical);
+		assert_eq!(
+			serde_json::to_string(&result.block).unwrap(),
+			r#"{"name":"Cpu","full_text":"error while parsing to type 'f32': oops","urgent":true}"#
+		);
+	}
+}
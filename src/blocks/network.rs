@@ -1,30 +1,38 @@
-use crate::blocks::{default_alpha, default_period, prelude::*, util};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{default_alpha, default_period, pause, prelude::*, util, Update};
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
 use futures_util::Stream;
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use tokio::time::{self, Duration};
+use tokio::time::{Duration, Instant};
 
-#[with_fields(alpha, period)]
-#[derive(Debug, Deserialize, GetName, PangoMarkup, IntoSerialized)]
+/// The values available to `format`, the receive and transmit rates in kB/s along with the theme's
+/// `net_down` and `net_up` icons.
+const VARIABLES: &[&str] = &["rx", "tx", "rx_icon", "tx_icon"];
+
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, GetName, PangoMarkup, IntoSerialized, OnClick)]
 pub struct Network {
 	path_to_rx: String,
 	path_to_tx: String,
 }
 
+fn default_format() -> Template {
+	"{rx_icon} {rx:.1} {tx_icon} {tx:.1}".parse().unwrap()
+}
+
 struct NetworkSpeed {
 	curr: f32,
 	prev: f32,
-	coef: f32,
 }
 
 impl NetworkSpeed {
-	fn new(coef: f32) -> NetworkSpeed {
+	fn new() -> NetworkSpeed {
 		NetworkSpeed {
 			curr: 0.0,
 			prev: 0.0,
-			coef,
 		}
 	}
 
@@ -33,30 +41,46 @@ impl NetworkSpeed {
 		self.curr = new;
 	}
 
-	fn calc_speed(&self) -> f32 {
-		(self.curr - self.prev) * self.coef
+	/// The speed in kB/s given the time between the last two pushes.
+	fn calc_speed(&self, elapsed: Duration) -> f32 {
+		(self.curr - self.prev) / (elapsed.as_secs_f32() * 1024.0)
 	}
 }
 
 impl IntoStream for Network {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let coef = 1.0 / (self.period as f32 * 1.024); // Report in kB/s (NB period is in ms)
-		let mut rx = NetworkSpeed::new(coef);
-		let mut tx = NetworkSpeed::new(coef);
-		let mut interval = time::interval(Duration::from_millis(self.period));
-		try_stream! {
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let mut rx = NetworkSpeed::new();
+		let mut tx = NetworkSpeed::new();
+		let period = Duration::from_millis(self.period);
+		Ok(try_stream! {
 			rx.push(util::read_to_ty(&self.path_to_rx).await?);
 			tx.push(util::read_to_ty(&self.path_to_tx).await?);
+			let mut then = Instant::now();
 			loop {
-				interval.tick().await;
+				// The wait is cut short when the bar continues after being stopped, so the speed is
+				// over the time which actually passed
+				pause::sleep(period).await;
+				let elapsed = then.elapsed();
+				then = Instant::now();
 				rx.push(util::read_to_ty(&self.path_to_rx).await?);
 				tx.push(util::read_to_ty(&self.path_to_tx).await?);
-				yield format!(
-					"<span foreground='#ccffcc'>  {:.1}</span> <span foreground='#ffcccc'>  {:.1}</span>",
-					rx.calc_speed(),
-					tx.calc_speed()
-				);
+				let (rx, tx) = (rx.calc_speed(elapsed), tx.calc_speed(elapsed));
+				let values = Values::new()
+					.with("rx", rx)
+					.with("tx", tx)
+					.with("rx_icon", theme::icon("net_down"))
+					.with("tx_icon", theme::icon("net_up"));
+				let text = self.format.render(&values);
+				// Thresholds apply to the total rate in kB/s
+				yield Update::new(text).with_value(rx + tx);
 			}
-		}
+		})
 	}
 }
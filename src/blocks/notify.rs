@@ -0,0 +1,192 @@
+use crate::Error;
+use futures_util::StreamExt;
+use inotify::{EventStream, Inotify, WatchMask};
+use nix::sys::statfs::{fstatfs, PROC_SUPER_MAGIC, SYSFS_MAGIC};
+use std::ffi::OsString;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, Interest};
+
+/// How changes to a watched file are noticed.
+enum Notifier {
+	/// Attributes on sysfs which support `sysfs_notify` signal a change with POLLPRI. Others never
+	/// signal, so waiting on them is the same as waiting on `Never`.
+	Priority(AsyncFd<std::fs::File>),
+	/// Files on ordinary filesystems are watched with inotify. The parent directory is watched
+	/// rather than the file itself so that files which are replaced rather than written to are
+	/// still picked up.
+	Inotify {
+		events: EventStream<Vec<u8>>,
+		name: OsString,
+	},
+	/// Files which can't notify, such as those on procfs, have to be polled.
+	Never,
+}
+
+/// A file which can be read repeatedly and waited on for changes.
+pub struct WatchedFile {
+	path: PathBuf,
+	notifier: Notifier,
+	file: File,
+}
+
+fn read_error(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+	|source| Error::Read {
+		path: path.display().to_string(),
+		source,
+	}
+}
+
+async fn priority(file: &File) -> io::Result<Notifier> {
+	// The duplicate shares the original's open file description, which is where sysfs keeps track
+	// of whether there's been a change since the file was last read
+	let file = file.try_clone().await?.into_std().await;
+	// SAFETY: The `AsyncFd` owns `file`, so its descriptor stays open and unchanged for as long as
+	// the `AsyncFd` exists
+	unsafe { AsyncFd::register_with_interest(file, Interest::PRIORITY) }
+		.map(Notifier::Priority)
+		.map_err(io::Error::from)
+}
+
+fn inotify(path: &Path, mask: WatchMask) -> io::Result<Notifier> {
+	let name = path.file_name().unwrap_or_default().to_owned();
+	let dir = match path.parent() {
+		Some(dir) if dir != Path::new("") => dir,
+		_ => Path::new("."),
+	};
+	let inotify = Inotify::init()?;
+	inotify.watches().add(dir, mask)?;
+	let events = inotify.into_event_stream(vec![0; 1024])?;
+	Ok(Notifier::Inotify { events, name })
+}
+
+impl WatchedFile {
+	pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let mask =
+			WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::CREATE | WatchMask::MOVED_TO;
+		Self::open_with(path, mask).await
+	}
+
+	/// Open a file which is only notified as changed once it's been saved, i.e. closed after
+	/// writing or moved into place, rather than on every write. An editor which truncates a file
+	/// before writing it would otherwise be seen to leave it empty.
+	pub async fn open_saved<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		Self::open_with(path, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO).await
+	}
+
+	async fn open_with<P: AsRef<Path>>(path: P, mask: WatchMask) -> Result<Self, Error> {
+		let path = path.as_ref().to_path_buf();
+		let file = File::open(&path).await.map_err(read_error(&path))?;
+		let notifier = match fstatfs(&file).map(|x| x.filesystem_type()) {
+			Ok(PROC_SUPER_MAGIC) => Notifier::Never,
+			Ok(SYSFS_MAGIC) => priority(&file).await.unwrap_or(Notifier::Never),
+			_ => inotify(&path, mask).unwrap_or(Notifier::Never),
+		};
+		Ok(Self {
+			path,
+			notifier,
+			file,
+		})
+	}
+
+	/// Read the whole file from the start into `buf`, replacing its contents and growing it as
+	/// needed. Returns the number of bytes read.
+	pub async fn read(&mut self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+		buf.clear();
+		if let Notifier::Inotify { .. } = self.notifier {
+			// The file may have been replaced since it was last read
+			self.file = File::open(&self.path)
+				.await
+				.map_err(read_error(&self.path))?;
+		} else {
+			self.file
+				.seek(SeekFrom::Start(0))
+				.await
+				.map_err(read_error(&self.path))?;
+		}
+		self.file
+			.read_to_end(buf)
+			.await
+			.map_err(read_error(&self.path))
+	}
+
+	/// Wait until the file is notified as having changed. For files which can't notify this never
+	/// returns, so it should be combined with a timeout.
+	pub async fn changed(&mut self) {
+		match &mut self.notifier {
+			Notifier::Priority(fd) => match fd.ready(Interest::PRIORITY).await {
+				Ok(mut guard) => guard.clear_ready(),
+				Err(_) => std::future::pending().await,
+			},
+			Notifier::Inotify { events, name } => {
+				while let Some(event) = events.next().await {
+					match event {
+						Ok(event) if event.name.as_ref() == Some(name) => return,
+						Ok(_) => {}
+						Err(_) => break,
+					}
+				}
+				std::future::pending().await
+			}
+			Notifier::Never => std::future::pending().await,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tokio::time::{timeout, Duration};
+
+	#[tokio::test]
+	async fn procfs_never_notifies() {
+		let file = WatchedFile::open("/proc/meminfo").await.unwrap();
+		assert!(matches!(file.notifier, Notifier::Never));
+	}
+
+	#[tokio::test]
+	async fn inotify_on_write() {
+		let dir = std::env::temp_dir().join(format!("rs-blocks-notify-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("file");
+		std::fs::write(&path, "one").unwrap();
+
+		let mut file = WatchedFile::open(&path).await.unwrap();
+		assert!(matches!(file.notifier, Notifier::Inotify { .. }));
+		let mut buf = Vec::new();
+		file.read(&mut buf).await.unwrap();
+		assert_eq!(buf, b"one");
+
+		std::fs::write(dir.join("other"), "x").unwrap();
+		std::fs::write(&path, "a longer line").unwrap();
+		timeout(Duration::from_secs(5), file.changed())
+			.await
+			.unwrap();
+		assert_eq!(file.read(&mut buf).await.unwrap(), 13);
+		assert_eq!(buf, b"a longer line");
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn notify_on_save() {
+		let dir = std::env::temp_dir().join(format!("rs-blocks-saved-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("file");
+		std::fs::write(&path, "one").unwrap();
+
+		let mut file = WatchedFile::open_saved(&path).await.unwrap();
+		let mut writer = std::fs::File::create(&path).unwrap();
+		std::io::Write::write_all(&mut writer, b"two").unwrap();
+		let unsaved = timeout(Duration::from_millis(100), file.changed()).await;
+		assert!(unsaved.is_err());
+		drop(writer);
+		timeout(Duration::from_secs(5), file.changed())
+			.await
+			.unwrap();
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,76 @@
+use std::future::{pending, Future};
+use std::sync::LazyLock;
+use tokio::sync::watch;
+use tokio::time::Duration;
+
+/// Whether the bar is stopped, which i3bar does while it's hidden, e.g. behind a fullscreen window.
+static STOPPED: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::Sender::new(false));
+
+/// Stop or continue every block. See `wait`.
+pub fn set_stopped(stopped: bool) {
+	STOPPED.send_replace(stopped);
+}
+
+/// Wait for `event` or for `period` to pass, whichever comes first, holding off for as long as the
+/// bar is stopped. Continuing ends the wait straight away, so that every block refreshes together.
+///
+/// Blocks wait between updates with this rather than with tokio's timers so that they're idle
+/// while the bar can't be seen.
+pub async fn wait<F: Future>(period: Option<Duration>, event: F) {
+	wait_on(STOPPED.subscribe(), period, event).await
+}
+
+/// Wait for `period` to pass, as with `wait`.
+pub async fn sleep(period: Duration) {
+	wait(Some(period), pending::<()>()).await
+}
+
+async fn wait_on<F: Future>(
+	mut stopped: watch::Receiver<bool>,
+	period: Option<Duration>,
+	event: F,
+) {
+	let timer = async {
+		match period {
+			Some(period) => tokio::time::sleep(period).await,
+			None => pending().await,
+		}
+	};
+	let mut continued = stopped.clone();
+	tokio::select! {
+		_ = event => {}
+		_ = timer => {}
+		// A stop followed by a continue, which returns without waiting for anything else
+		_ = async {
+			let _ = continued.wait_for(|x| *x).await;
+			let _ = continued.wait_for(|x| !x).await;
+		} => return,
+	}
+	// Anything which happened while stopped is seen once the bar continues
+	let _ = stopped.wait_for(|x| !x).await;
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tokio::time::{timeout, Instant};
+
+	#[tokio::test]
+	async fn waits_while_stopped() {
+		let (tx, rx) = watch::channel(false);
+		let period = Duration::from_millis(10);
+		let start = Instant::now();
+		wait_on(rx.clone(), Some(period), pending::<()>()).await;
+		assert!(start.elapsed() >= period);
+
+		tx.send_replace(true);
+		let waiting = wait_on(rx.clone(), Some(period), async {});
+		assert!(timeout(5 * period, waiting).await.is_err());
+
+		// Continuing returns straight away rather than waiting out the period
+		let start = Instant::now();
+		let waiting = wait_on(rx, Some(Duration::from_secs(60)), pending::<()>());
+		tokio::join!(waiting, async { tx.send_replace(false) });
+		assert!(start.elapsed() < Duration::from_secs(1));
+	}
+}
@@ -3,107 +3,131 @@ use futures_util::ready;
 use futures_util::stream::Stream;
 use futures_util::task::{Context, Poll};
 use pin_project::pin_project;
-use std::future::Future;
 
+/// Passes on only the `Ok` items which differ from the last one, so that a block polling on a
+/// timer doesn't output the same thing every tick. Items are compared by the key `key` gives for
+/// them. Errors always pass through, and the item after an error is always passed on since the
+/// error will have taken its place on the bar.
 #[pin_project]
-pub struct Periodise<St, F, Fut> {
+pub struct OnChanges<St, F, K> {
 	#[pin]
 	stream: St,
-	waiter_factory: F,
-	#[pin]
-	future: Option<Fut>,
+	key: F,
+	prev: Option<K>,
 }
 
-impl<St, F, Fut> Periodise<St, F, Fut> {
-	pub fn new(stream: St, waiter_factory: F) -> Self {
+impl<St, F, K> OnChanges<St, F, K> {
+	pub fn new(stream: St, key: F) -> Self {
 		Self {
 			stream,
-			waiter_factory,
-			future: None,
+			key,
+			prev: None,
 		}
 	}
 }
 
-impl<St, F, Fut> Stream for Periodise<St, F, Fut>
+impl<St, F, K, T, E> Stream for OnChanges<St, F, K>
 where
-	St: Stream,
-	F: Fn() -> Fut,
-	Fut: Future<Output = ()>,
+	St: Stream<Item = Result<T, E>>,
+	F: Fn(&T) -> K,
+	K: PartialEq,
 {
 	type Item = St::Item;
 
 	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
 		let mut this = self.project();
-		if let Some(fut) = this.future.as_mut().as_pin_mut() {
-			ready!(fut.poll(cx));
+		loop {
+			match ready!(this.stream.as_mut().poll_next(cx)) {
+				Some(Ok(item)) => {
+					let key = (this.key)(&item);
+					if this.prev.as_ref() != Some(&key) {
+						*this.prev = Some(key);
+						return Poll::Ready(Some(Ok(item)));
+					}
+				}
+				Some(Err(e)) => {
+					*this.prev = None;
+					return Poll::Ready(Some(Err(e)));
+				}
+				None => return Poll::Ready(None),
+			}
 		}
-		this.future.set(Some((this.waiter_factory)()));
-		this.stream.as_mut().poll_next(cx)
 	}
 }
 
-// This stream extension is currently unused because the return types from the streams in this
-// project are `Result<_, Error>` where `Error` does not implement `Clone`. If I can find a way
-// to specify that the trait bound for `Clone` is only actually required on the `Ok` variant of
-// the stream item, then this would be a nice alternative to implementing the "on changes"
-// functionailty within `util::watch`.
+/// Polls a stream inside a tracing span, so that anything logged while producing its items is
+/// attributed to the span.
 #[pin_project]
-pub struct OnChanges<St>
-where
-	St: Stream,
-{
+pub struct Instrumented<St> {
 	#[pin]
 	stream: St,
-	prev: Option<<St as Stream>::Item>,
+	span: tracing::Span,
 }
 
-impl<St: Stream> OnChanges<St> {
-	pub fn new(stream: St) -> Self {
-		Self { stream, prev: None }
+impl<St> Instrumented<St> {
+	pub fn new(stream: St, span: tracing::Span) -> Self {
+		Self { stream, span }
 	}
 }
 
-impl<St> Stream for OnChanges<St>
-where
-	St: Stream,
-	<St as Stream>::Item: PartialEq + Clone,
-{
+impl<St: Stream> Stream for Instrumented<St> {
 	type Item = St::Item;
 
 	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-		let mut this = self.project();
-		loop {
-			if let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
-				if let Some(prev) = this.prev.replace(item.clone()) {
-					if prev != item {
-						return Poll::Ready(Some(item));
-					}
-				} else {
-					return Poll::Ready(Some(item)); // Always return on first read
-				}
-			} else {
-				return Poll::Ready(None);
-			}
-		}
+		let this = self.project();
+		let _enter = this.span.enter();
+		this.stream.poll_next(cx)
 	}
 }
 
 pub trait StreamExt2: Stream {
-	fn with_period<F, Fut>(self, f: F) -> Periodise<Self, F, Fut>
+	fn on_changes<T, E, F, K>(self, key: F) -> OnChanges<Self, F, K>
 	where
-		Self: Sized,
-		F: Fn() -> Fut,
-		Fut: Future<Output = ()>,
+		Self: Stream<Item = Result<T, E>> + Sized,
+		F: Fn(&T) -> K,
 	{
-		Periodise::new(self, f)
+		OnChanges::new(self, key)
 	}
 
-	fn on_changes(self) -> OnChanges<Self>
+	fn instrument(self, span: tracing::Span) -> Instrumented<Self>
 	where
 		Self: Sized,
 	{
-		OnChanges::new(self)
+		Instrumented::new(self, span)
 	}
 }
 
 impl<T: ?Sized> StreamExt2 for T where T: Stream {}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures_util::stream::{self, StreamExt};
+
+	#[test]
+	fn on_changes() {
+		let items = vec![
+			Ok((1, 'a')),
+			Ok((1, 'b')),
+			Ok((2, 'c')),
+			Err("e"),
+			Ok((2, 'd')),
+			Ok((2, 'e')),
+			Err("e"),
+			Err("e"),
+		];
+		let changes = stream::iter(items).on_changes(|x: &(i32, char)| x.0);
+		let changes: Vec<_> = futures::executor::block_on(changes.collect());
+		assert_eq!(
+			changes,
+			vec![
+				Ok((1, 'a')),
+				Ok((2, 'c')),
+				Err("e"),
+				Ok((2, 'd')),
+				Err("e"),
+				Err("e")
+			]
+		);
+	}
+}
@@ -0,0 +1,270 @@
+use crate::blocks::format::{Template, Values};
+use crate::blocks::{default_alpha, pause, prelude::*, util, Update};
+use crate::theme;
+use crate::Error;
+use async_stream::try_stream;
+use futures_util::Stream;
+use rs_blocks_macros::*;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::time::Duration;
+
+/// The values available to `format`, where `icon` is the theme's `temperature` icon and `unit` is
+/// `°C` or `°F`.
+const VARIABLES: &[&str] = &["icon", "temperature", "unit"];
+
+/// The temperature of the hottest of the selected sensors, or their average. Sensors are the
+/// `temp*_input` files of hwmon chips and the thermal zones.
+#[with_fields(alpha, period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized, OnClick)]
+pub struct Temperature {
+	#[serde(default = "default_hwmon_path")]
+	hwmon_path: String,
+	#[serde(default = "default_thermal_path")]
+	thermal_path: String,
+	/// The chips (a hwmon `name` or thermal zone `type`) or sensor labels to use, or every sensor
+	/// if empty.
+	#[serde(default)]
+	sensors: Vec<String>,
+	#[serde(default)]
+	unit: Unit,
+	#[serde(default)]
+	combine: Combine,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+	#[default]
+	Celsius,
+	Fahrenheit,
+}
+
+impl Unit {
+	fn convert(self, celsius: f32) -> f32 {
+		match self {
+			Unit::Celsius => celsius,
+			Unit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+		}
+	}
+
+	fn symbol(self) -> &'static str {
+		match self {
+			Unit::Celsius => "\u{b0}C",
+			Unit::Fahrenheit => "\u{b0}F",
+		}
+	}
+}
+
+/// How the temperatures of several sensors are combined into one.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Combine {
+	#[default]
+	Max,
+	Average,
+}
+
+impl Combine {
+	fn apply(self, temperatures: &[f32]) -> Option<f32> {
+		match self {
+			_ if temperatures.is_empty() => None,
+			Combine::Max => temperatures.iter().copied().reduce(f32::max),
+			Combine::Average => Some(temperatures.iter().sum::<f32>() / temperatures.len() as f32),
+		}
+	}
+}
+
+fn default_hwmon_path() -> String {
+	"/sys/class/hwmon".to_string()
+}
+
+fn default_thermal_path() -> String {
+	"/sys/class/thermal".to_string()
+}
+
+fn default_period() -> u64 {
+	2000
+}
+
+fn default_format() -> Template {
+	"{icon} {temperature:.0}{unit}".parse().unwrap()
+}
+
+/// A file holding a temperature in millidegrees Celsius.
+#[derive(Clone, Debug, PartialEq)]
+struct Sensor {
+	chip: String,
+	label: Option<String>,
+	path: String,
+}
+
+impl Sensor {
+	fn matches(&self, names: &[String]) -> bool {
+		names.is_empty()
+			|| names
+				.iter()
+				.any(|x| *x == self.chip || Some(x) == self.label.as_ref())
+	}
+}
+
+/// Read a sysfs attribute such as a chip's name, which is missing for some devices.
+fn read_name(path: &Path) -> Option<String> {
+	fs::read_to_string(path).ok().map(|x| x.trim().to_string())
+}
+
+/// The subdirectories of `path` whose names start with `prefix`, sorted so that sensors are
+/// always found in the same order.
+fn subdirs(path: &str, prefix: &str) -> Vec<PathBuf> {
+	let Ok(entries) = fs::read_dir(path) else {
+		return Vec::new();
+	};
+	let mut dirs: Vec<_> = entries
+		.filter_map(|x| x.ok())
+		.filter(|x| x.file_name().to_string_lossy().starts_with(prefix))
+		.map(|x| x.path())
+		.collect();
+	dirs.sort();
+	dirs
+}
+
+/// Every sensor of every hwmon chip under `hwmon_path` and every thermal zone under
+/// `thermal_path`.
+fn discover(hwmon_path: &str, thermal_path: &str) -> Vec<Sensor> {
+	let mut sensors = Vec::new();
+	for dir in subdirs(hwmon_path, "hwmon") {
+		let chip = read_name(&dir.join("name")).unwrap_or_default();
+		let Ok(entries) = fs::read_dir(&dir) else {
+			continue;
+		};
+		let mut inputs: Vec<_> = entries
+			.filter_map(|x| x.ok())
+			.filter_map(|x| {
+				let name = x.file_name().to_string_lossy().into_owned();
+				let sensor: u32 = name
+					.strip_prefix("temp")?
+					.strip_suffix("_input")?
+					.parse()
+					.ok()?;
+				Some((sensor, x.path()))
+			})
+			.collect();
+		inputs.sort();
+		for (sensor, path) in inputs {
+			sensors.push(Sensor {
+				chip: chip.clone(),
+				label: read_name(&dir.join(format!("temp{sensor}_label"))),
+				path: path.to_string_lossy().into_owned(),
+			});
+		}
+	}
+	for dir in subdirs(thermal_path, "thermal_zone") {
+		sensors.push(Sensor {
+			chip: read_name(&dir.join("type")).unwrap_or_default(),
+			label: None,
+			path: dir.join("temp").to_string_lossy().into_owned(),
+		});
+	}
+	sensors
+}
+
+impl IntoStream for Temperature {
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let sensors: Vec<Sensor> = discover(&self.hwmon_path, &self.thermal_path)
+			.into_iter()
+			.filter(|x| x.matches(&self.sensors))
+			.collect();
+		if sensors.is_empty() {
+			return Err(Error::NoSensors(match self.sensors.is_empty() {
+				true => format!("under '{}' or '{}'", self.hwmon_path, self.thermal_path),
+				false => format!("named '{}'", self.sensors.join("', '")),
+			}));
+		}
+		tracing::debug!(?sensors, "found sensors");
+		let period = Duration::from_millis(self.period);
+		let mut ema = util::Ema::new(self.alpha);
+		Ok(try_stream! {
+			loop {
+				let mut temperatures = Vec::new();
+				for sensor in &sensors {
+					let millidegrees: f32 = util::read_to_ty(&sensor.path).await?;
+					temperatures.push(millidegrees / 1000.0);
+				}
+				if let Some(celsius) = self.combine.apply(&temperatures) {
+					let temperature = self.unit.convert(ema.push(celsius));
+					let values = Values::new()
+						.with("icon", theme::icon("temperature"))
+						.with("temperature", temperature)
+						.with("unit", self.unit.symbol());
+					let text = self.format.render(&values);
+					yield Update::new(text).with_value(temperature);
+				}
+				pause::sleep(period).await;
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn discover_sensors() {
+		let root =
+			std::env::temp_dir().join(format!("rs-blocks-temperature-{}", std::process::id()));
+		let hwmon = root.join("hwmon/hwmon0");
+		let zone = root.join("thermal/thermal_zone0");
+		fs::create_dir_all(&hwmon).unwrap();
+		fs::create_dir_all(&zone).unwrap();
+		fs::write(hwmon.join("name"), "coretemp\n").unwrap();
+		fs::write(hwmon.join("temp1_input"), "50000\n").unwrap();
+		fs::write(hwmon.join("temp1_label"), "Package id 0\n").unwrap();
+		fs::write(hwmon.join("temp2_input"), "40000\n").unwrap();
+		fs::write(zone.join("type"), "acpitz\n").unwrap();
+		fs::write(zone.join("temp"), "30000\n").unwrap();
+
+		let sensors = discover(
+			&root.join("hwmon").to_string_lossy(),
+			&root.join("thermal").to_string_lossy(),
+		);
+		fs::remove_dir_all(&root).unwrap();
+		let names: Vec<_> = sensors
+			.iter()
+			.map(|x| (x.chip.as_str(), x.label.as_deref()))
+			.collect();
+		assert_eq!(
+			names,
+			vec![
+				("coretemp", Some("Package id 0")),
+				("coretemp", None),
+				("acpitz", None)
+			]
+		);
+		let selected = |names: &[&str]| {
+			let names: Vec<String> = names.iter().map(|x| x.to_string()).collect();
+			sensors.iter().filter(|x| x.matches(&names)).count()
+		};
+		assert_eq!(selected(&[]), 3);
+		assert_eq!(selected(&["coretemp"]), 2);
+		assert_eq!(selected(&["Package id 0", "acpitz"]), 2);
+		assert_eq!(selected(&["k10temp"]), 0);
+	}
+
+	#[test]
+	fn combine_and_convert() {
+		assert_eq!(Combine::Max.apply(&[40.0, 60.0]), Some(60.0));
+		assert_eq!(Combine::Average.apply(&[40.0, 60.0]), Some(50.0));
+		assert_eq!(Combine::Average.apply(&[]), None);
+		assert_eq!(Unit::Fahrenheit.convert(100.0), 212.0);
+		assert_eq!(Unit::Celsius.convert(100.0), 100.0);
+	}
+}
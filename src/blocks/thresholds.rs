@@ -0,0 +1,236 @@
+use crate::blocks::Update;
+use crate::theme::Palette;
+use serde::Deserialize;
+use std::fmt;
+
+/// The state of a block, which picks its colours from the theme's palette. Warning and critical
+/// come from a block's thresholds, whereas info and good are set by blocks themselves, e.g. for a
+/// battery which is charging.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum State {
+	#[default]
+	Idle,
+	Info,
+	Good,
+	Warning,
+	Critical,
+}
+
+impl State {
+	pub fn name(self) -> &'static str {
+		match self {
+			State::Idle => "idle",
+			State::Info => "info",
+			State::Good => "good",
+			State::Warning => "warning",
+			State::Critical => "critical",
+		}
+	}
+}
+
+/// An RGB colour, written `#rrggbb` in the config.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgb {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		let invalid = || format!("invalid colour '{value}', expected '#rrggbb'");
+		let hex = value
+			.strip_prefix('#')
+			.filter(|x| x.len() == 6 && x.bytes().all(|b| b.is_ascii_hexdigit()))
+			.ok_or_else(invalid)?;
+		let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+		Ok(Self(channel(0)?, channel(2)?, channel(4)?))
+	}
+}
+
+impl fmt::Display for Rgb {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+	}
+}
+
+impl Rgb {
+	/// The colour a fraction `t` of the way from `self` to `other`.
+	fn mix(self, other: Self, t: f64) -> Self {
+		let channel =
+			|a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as u8;
+		Self(
+			channel(self.0, other.0),
+			channel(self.1, other.1),
+			channel(self.2, other.2),
+		)
+	}
+}
+
+/// Colouring of a block according to its value, configured per block alongside its attributes:
+///
+/// ```toml
+/// [Cpu]
+/// warning = 70
+/// critical = 90
+/// gradient = [[0, "#00ff00"], [50, "#ffff00"], [100, "#ff0000"]]
+/// ```
+///
+/// Values at or beyond `warning` or `critical` are shown in the theme's colours for that state,
+/// with critical values also marked urgent. When `critical` is below `warning` it's low values
+/// which are bad, as with a battery's charge. Otherwise the text is coloured along the gradient,
+/// whose stops are pairs of a value and the colour at that value.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Thresholds {
+	pub warning: Option<f64>,
+	pub critical: Option<f64>,
+	pub gradient: Option<Vec<(f64, Rgb)>>,
+}
+
+impl Thresholds {
+	/// Fill any settings which are unset in `self` from `defaults`.
+	pub fn or(self, defaults: &Thresholds) -> Thresholds {
+		Thresholds {
+			warning: self.warning.or(defaults.warning),
+			critical: self.critical.or(defaults.critical),
+			gradient: self.gradient.or_else(|| defaults.gradient.clone()),
+		}
+	}
+
+	pub fn state(&self, value: f64) -> State {
+		let low_is_bad = matches!((self.warning, self.critical), (Some(w), Some(c)) if c < w);
+		let crossed = |threshold: Option<f64>| match threshold {
+			Some(x) if low_is_bad => value <= x,
+			Some(x) => value >= x,
+			None => false,
+		};
+		if crossed(self.critical) {
+			State::Critical
+		} else if crossed(self.warning) {
+			State::Warning
+		} else {
+			State::Idle
+		}
+	}
+
+	fn gradient(&self, value: f64) -> Option<Rgb> {
+		let mut stops = self.gradient.clone()?;
+		stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+		let first = stops.first()?;
+		if value <= first.0 {
+			return Some(first.1);
+		}
+		for pair in stops.windows(2) {
+			let ((a, from), (b, to)) = (pair[0], pair[1]);
+			if value <= b {
+				return Some(from.mix(to, (value - a) / (b - a)));
+			}
+		}
+		stops.last().map(|x| x.1)
+	}
+
+	/// Colour `update` according to its value and state, taking colours from `palette`. The i3bar
+	/// `color` and `background` are set unless the block or its config already set them, and each
+	/// output converts them for its bar.
+	pub fn apply(&self, update: &mut Update, palette: &Palette) {
+		if update.value.is_none() && update.state.is_none() {
+			return;
+		}
+		// Crossing a threshold takes precedence over the state a block sets itself
+		let state = match update.value.map(|x| self.state(x)) {
+			Some(State::Idle) | None => update.state.unwrap_or(State::Idle),
+			Some(state) => state,
+		};
+		update.state = Some(state);
+		let colours = palette.colours(state);
+		let gradient = match state {
+			State::Idle => update.value.and_then(|x| self.gradient(x)),
+			_ => None,
+		};
+		let (color, background) = (gradient.or(colours.fg), colours.bg);
+		if state == State::Critical {
+			update.attributes.urgent = Some(true);
+		}
+		let attributes = &mut update.attributes;
+		attributes.color = attributes.color.take().or(color.map(|x| x.to_string()));
+		attributes.background = attributes
+			.background
+			.take()
+			.or(background.map(|x| x.to_string()));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn thresholds(string: &str) -> Thresholds {
+		toml::from_str(string).unwrap()
+	}
+
+	#[test]
+	fn states() {
+		let high = thresholds("warning = 70\ncritical = 90");
+		assert_eq!(high.state(50.0), State::Idle);
+		assert_eq!(high.state(70.0), State::Warning);
+		assert_eq!(high.state(95.0), State::Critical);
+
+		let low = thresholds("warning = 20\ncritical = 10");
+		assert_eq!(low.state(50.0), State::Idle);
+		assert_eq!(low.state(15.0), State::Warning);
+		assert_eq!(low.state(5.0), State::Critical);
+
+		assert_eq!(Thresholds::default().state(100.0), State::Idle);
+	}
+
+	#[test]
+	fn gradient() {
+		let gradient = thresholds("gradient = [[100, '#00ff00'], [0, '#ff0000'], [50, '#ffff00']]");
+		assert_eq!(gradient.gradient(-1.0), Some(Rgb(0xff, 0, 0)));
+		assert_eq!(gradient.gradient(1.0), Some(Rgb(0xff, 0x05, 0)));
+		assert_eq!(gradient.gradient(75.0), Some(Rgb(0x80, 0xff, 0)));
+		assert_eq!(gradient.gradient(200.0), Some(Rgb(0, 0xff, 0)));
+		assert_eq!(Thresholds::default().gradient(1.0), None);
+		assert!(toml::from_str::<Thresholds>("gradient = [[0, 'red']]").is_err());
+		assert!(toml::from_str::<Thresholds>("gradient = [[0, '#aééb']]").is_err());
+	}
+
+	fn apply(thresholds: &Thresholds, update: &mut Update) {
+		thresholds.apply(update, &crate::theme::Theme::default().palette);
+	}
+
+	#[test]
+	fn colours() {
+		let thresholds = thresholds(
+			"warning = 70\ncritical = 90\ngradient = [[0, '#000000'], [100, '#ffffff']]",
+		);
+		let update = |value| Update {
+			value: Some(value),
+			..Update::new("text".to_string())
+		};
+
+		let mut idle = update(50.0);
+		apply(&thresholds, &mut idle);
+		assert_eq!(idle.attributes.color.as_deref(), Some("#808080"));
+		assert_eq!(idle.attributes.urgent, None);
+
+		let mut critical = update(95.0);
+		apply(&thresholds, &mut critical);
+		assert_eq!(critical.attributes.color.as_deref(), Some("#ffffff"));
+		assert_eq!(critical.attributes.background.as_deref(), Some("#cc0000"));
+		assert_eq!(critical.attributes.urgent, Some(true));
+
+		let mut warning = update(80.0);
+		warning.attributes.color = Some("#123456".to_string());
+		apply(&thresholds, &mut warning);
+		assert_eq!(warning.attributes.color.as_deref(), Some("#123456"));
+		assert_eq!(warning.state, Some(State::Warning));
+
+		let mut good = update(10.0).with_state(State::Good);
+		apply(&thresholds, &mut good);
+		assert_eq!(good.attributes.color.as_deref(), Some("#a3be8c"));
+
+		let mut no_value = Update::new("text".to_string());
+		apply(&thresholds, &mut no_value);
+		assert_eq!(no_value, Update::new("text".to_string()));
+	}
+}
@@ -1,14 +1,17 @@
-use crate::blocks::prelude::*;
+use crate::blocks::{pause, prelude::*, Update};
 use crate::Error;
 use async_stream::try_stream;
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
 use futures_util::Stream;
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use tokio::time::{self, Duration};
+use tokio::time::Duration;
 
+/// The current date and time. Unlike other blocks, `format` is a strftime string as understood by
+/// `chrono`, e.g. `%H:%M`.
 #[with_fields(period)]
-#[derive(Debug, Deserialize, GetName, PangoMarkup, IntoSerialized)]
+#[derive(Clone, Debug, Deserialize, PartialEq, GetName, PangoMarkup, IntoSerialized, OnClick)]
 pub struct Time {
 	#[serde(default = "default_format")]
 	format: String,
@@ -23,13 +26,43 @@ fn default_format() -> String {
 }
 
 impl IntoStream for Time {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let mut interval = time::interval(Duration::from_millis(self.period));
-		try_stream! {
+	/// Chrono panics when formatting the time with an invalid strftime string, so one is rejected
+	/// here instead.
+	fn check(&self) -> Result<(), Error> {
+		if StrftimeItems::new(&self.format).any(|x| x == Item::Error) {
+			return Err(Error::Parse {
+				ty: "strftime format",
+				reason: format!("invalid format '{}'", self.format),
+			});
+		}
+		Ok(())
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let period = Duration::from_millis(self.period);
+		Ok(try_stream! {
 			loop {
 				yield Local::now().format(&self.format).to_string();
-				interval.tick().await;
+				pause::sleep(period).await;
 			}
-		}
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn check() {
+		let time = |format: &str| Time {
+			format: format.to_string(),
+			period: default_period(),
+		};
+		assert!(time("%a %d %b <b>%H:%M:%S</b>").check().is_ok());
+		assert!(time("%Q %H").check().is_err());
 	}
 }
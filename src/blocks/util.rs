@@ -1,14 +1,15 @@
+use crate::blocks::notify::WatchedFile;
+use crate::blocks::pause;
 use crate::Error;
 use async_stream::stream;
 use futures_util::Stream;
+use itertools::Itertools;
 use std::fmt::{self, Display, Formatter};
 use std::ops::{Add, Mul, Sub};
 use std::path::Path;
 use std::str::FromStr;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio::io::AsyncSeekExt;
-use tokio::io::SeekFrom;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::time::Duration;
 
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub struct Ema<T: PartialEq> {
@@ -55,27 +56,110 @@ where
 	}
 }
 
-pub fn watch<P, const CAPACITY: usize>(path: P) -> impl Stream<Item = Result<String, Error>>
+/// Format a number of bytes in binary units, e.g. `3.2G`, with one decimal place below 10.
+pub fn human_bytes(bytes: f64) -> String {
+	const UNITS: &[&str] = &["B", "K", "M", "G", "T", "P"];
+	let mut value = bytes;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	match value < 10.0 && unit > 0 {
+		true => format!("{value:.1}{}", UNITS[unit]),
+		false => format!("{value:.0}{}", UNITS[unit]),
+	}
+}
+
+/// Render percentages as a line of bars, one per value, e.g. `▂▅▇▃`.
+pub fn sparkline(percents: impl IntoIterator<Item = f32>) -> String {
+	const BARS: [char; 8] = [
+		'\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}',
+		'\u{2588}',
+	];
+	percents
+		.into_iter()
+		.map(|x| BARS[(x.clamp(0.0, 100.0) / 100.0 * 7.0).round() as usize])
+		.collect()
+}
+
+/// Watch a file, yielding its contents whenever they change.
+///
+/// Changes are picked up as soon as they're notified where the file supports it (see
+/// `notify::WatchedFile`). Otherwise, and as a fallback, the file is re-read every `period`.
+pub fn watch<P>(path: P, period: Duration) -> impl Stream<Item = Result<String, Error>>
 where
 	P: AsRef<Path> + Copy,
+{
+	watch_with(path, period, str::to_owned)
+}
+
+/// Watch the lines of a file which begin with one of `keys`, yielding just those lines whenever
+/// any of them change.
+///
+/// This suits files like `/proc/meminfo` or `/proc/stat`, where only a few of many lines are of
+/// interest and the rest change constantly. See `select_keys` for how lines are matched.
+pub fn watch_keys<P>(
+	path: P,
+	period: Duration,
+	keys: &'static [&'static str],
+) -> impl Stream<Item = Result<String, Error>>
+where
+	P: AsRef<Path> + Copy,
+{
+	watch_with(path, period, move |contents| select_keys(contents, keys))
+}
+
+fn watch_with<P, F>(
+	path: P,
+	period: Duration,
+	select: F,
+) -> impl Stream<Item = Result<String, Error>>
+where
+	P: AsRef<Path> + Copy,
+	F: Fn(&str) -> String,
 {
 	stream! {
-		let mut file = File::open(path).await?;
-		let mut buf = [0; CAPACITY];
-		let mut current = [0; CAPACITY];
+		let mut file = WatchedFile::open(path).await?;
+		let mut buf = Vec::new();
+		let mut current = None;
 		loop {
-			let n = file.read(&mut buf[..]).await?;
-			if buf != current {
-				current = buf;
-				yield std::str::from_utf8(&current[..n])
-					.map(|x| x.to_owned())
-					.map_err(|e| Error::Parse { ty: "UTF-8 string", reason: e.to_string() });
+			file.read(&mut buf).await?;
+			match std::str::from_utf8(&buf) {
+				Ok(contents) => {
+					let selected = select(contents);
+					if current.as_ref() != Some(&selected) {
+						current = Some(selected.clone());
+						yield Ok(selected);
+					}
+				}
+				Err(e) => yield Err(Error::Parse { ty: "UTF-8 string", reason: e.to_string() }),
 			}
-			file.seek(SeekFrom::Start(0)).await?;
+			pause::wait(Some(period), file.changed()).await;
 		}
 	}
 }
 
+/// Select the lines of `contents` whose key is one of `keys`, keeping them in file order.
+///
+/// A line's key is its first word with any trailing `:` removed, so `MemFree` matches
+/// `MemFree:  1024 kB` and `cpu` matches the aggregate line of `/proc/stat` but not `cpu0`.
+pub fn select_keys(contents: &str, keys: &[&str]) -> String {
+	let lines = contents.lines().filter(|line| {
+		let key = line.split_whitespace().next().unwrap_or_default();
+		keys.contains(&key.trim_end_matches(':'))
+	});
+	Itertools::intersperse(lines, "\n").collect()
+}
+
+/// Listen for the signal with the given raw value.
+pub fn signal_stream(raw: i32) -> Result<Signal, Error> {
+	signal(SignalKind::from_raw(raw)).map_err(|source| Error::Signal {
+		signal: raw,
+		source,
+	})
+}
+
 /// Read to Type
 ///
 /// A convenience function to read a file to a given type `T`.
@@ -85,14 +169,18 @@ where
 	T: FromStr,
 	<T as FromStr>::Err: ToString,
 {
-	let contents = tokio::fs::read_to_string(&path).await?;
-	contents
-		.trim()
-		.parse()
-		.map_err(|e: <T as FromStr>::Err| Error::Parse {
-			ty: std::any::type_name::<T>(),
-			reason: e.to_string(),
-		})
+	let contents = tokio::fs::read_to_string(&path)
+		.await
+		.map_err(|source| Error::Read {
+			path: path.to_string(),
+			source,
+		})?;
+	contents.trim().parse().map_err(|e: <T as FromStr>::Err| {
+		let ty = std::any::type_name::<T>();
+		let reason = e.to_string();
+		tracing::warn!(%path, ty, contents = contents.trim(), %reason, "parse failed");
+		Error::Parse { ty, reason }
+	})
 }
 
 /// From String
@@ -103,14 +191,58 @@ pub fn from_string<'a, T>(re: &regex::Regex, contents: &'a str) -> Result<T, Err
 where
 	T: TryFrom<regex::Captures<'a>, Error = String>,
 {
-	re.captures(contents)
-		.ok_or_else(|| Error::Parse {
-			ty: std::any::type_name::<T>(),
-			reason: "regex pattern match failed".to_string(),
-		})
-		.map(|x| x.try_into())?
-		.map_err(|e| Error::Parse {
-			ty: std::any::type_name::<T>(),
-			reason: e,
-		})
+	let ty = std::any::type_name::<T>();
+	let result = re
+		.captures(contents)
+		.ok_or_else(|| "regex pattern match failed".to_string())
+		.and_then(|x| x.try_into());
+	result.map_err(|reason| {
+		tracing::warn!(ty, pattern = re.as_str(), contents, %reason, "parse failed");
+		Error::Parse { ty, reason }
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures_util::{pin_mut, StreamExt};
+
+	#[test]
+	fn select_keys_in_file_order() {
+		let contents = "cpu  1 2 3\ncpu0 1 2 3\nMemTotal: 10 kB\nMemFree:\t5 kB\n";
+		assert_eq!(
+			select_keys(contents, &["MemFree", "cpu", "MemTotal"]),
+			"cpu  1 2 3\nMemTotal: 10 kB\nMemFree:\t5 kB"
+		);
+		assert_eq!(select_keys(contents, &["Swap"]), "");
+	}
+
+	#[test]
+	fn human_units() {
+		assert_eq!(human_bytes(512.0), "512B");
+		assert_eq!(human_bytes(3.0 * 1024.0 * 1024.0 * 1024.0 + 2e8), "3.2G");
+		assert_eq!(human_bytes(812.0 * 1024.0 * 1024.0), "812M");
+		assert_eq!(human_bytes(2048.0f64.powi(6)), "65536P");
+	}
+
+	#[test]
+	fn sparklines() {
+		assert_eq!(
+			sparkline([0.0, 50.0, 90.0, 100.0, 150.0]),
+			"\u{2581}\u{2585}\u{2587}\u{2588}\u{2588}"
+		);
+		assert_eq!(sparkline([]), "");
+	}
+
+	#[tokio::test]
+	async fn watch_whole_file() {
+		// Comfortably longer than any fixed buffer the blocks used to read into
+		let watcher = watch("/proc/self/status", Duration::from_secs(60));
+		pin_mut!(watcher);
+		let contents = watcher.next().await.unwrap().unwrap();
+		assert!(contents.len() > 100);
+		assert!(contents
+			.trim_end()
+			.ends_with(&select_keys(&contents, &["nonvoluntary_ctxt_switches"])));
+	}
 }
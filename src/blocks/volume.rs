@@ -1,18 +1,28 @@
-use crate::blocks::{prelude::*, util};
+use crate::blocks::format::{Template, Values};
+use crate::blocks::thresholds::State;
+use crate::blocks::{pause, prelude::*, util, Update};
+use crate::click::ClickEvent;
+use crate::theme;
 use crate::Error;
 use async_stream::try_stream;
 use futures_util::Stream;
 use rs_blocks_macros::*;
 use serde::Deserialize;
-use std::fmt::{self, Display, Formatter};
+use std::sync::LazyLock;
 use tokio::process::Command;
-use tokio::signal::unix::{signal, SignalKind};
+use tokio::signal::unix::SignalKind;
 
 const AUDIO_DRIVER_COMMAND: &str = "pulsemixer";
-const PATTERN: &str = r"(?<mute>\d)\n(?<level>\d+)";
+static PATTERN: LazyLock<regex::Regex> =
+	LazyLock::new(|| regex::Regex::new(r"(?<mute>\d)\n(?<level>\d+)").unwrap());
 
-#[with_fields(period)]
-#[derive(Debug, Deserialize, NoMarkup, GetName, IntoSerialized)]
+/// The values available to `format`. `volume` is missing while muted, and `muted` is empty while
+/// muted and missing otherwise, so that sections can show something for just one of the states.
+/// `icon` is the theme's `volume` or `volume_muted` icon.
+const VARIABLES: &[&str] = &["icon", "volume", "muted"];
+
+#[with_fields(period, format)]
+#[derive(Clone, Debug, Deserialize, PartialEq, NoMarkup, GetName, IntoSerialized)]
 pub struct Volume {
 	#[serde(default = "default_update_signal")]
 	update_signal: i32,
@@ -26,43 +36,88 @@ fn default_update_signal() -> i32 {
 	SignalKind::user_defined2().as_raw_value()
 }
 
+fn default_format() -> Template {
+	"{icon}[ {volume}%]".parse().unwrap()
+}
+
 #[derive(TryFromCaptures)]
 struct VolumeStats {
 	mute: u8,
 	level: u8,
 }
 
-impl Display for VolumeStats {
-	fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
-		let text = if self.mute == 1 {
-			""
-		} else {
-			&format!("   {}%", self.level)
-		};
-		write!(f, "{}", text)
+impl VolumeStats {
+	fn values(&self) -> Values {
+		let muted = self.mute == 1;
+		let icon = if muted { "volume_muted" } else { "volume" };
+		Values::new()
+			.with("icon", theme::icon(icon))
+			.with("volume", (!muted).then_some(u32::from(self.level)))
+			.with("muted", muted.then_some(""))
+	}
+}
+
+/// Run the audio driver with the given arguments, returning its stdout.
+async fn run_driver(args: &[&str]) -> Result<String, Error> {
+	Command::new(AUDIO_DRIVER_COMMAND)
+		.args(args)
+		.output()
+		.await
+		.map(|x| String::from_utf8(x.stdout))?
+		.map_err(|e| Error::Parse {
+			ty: "UTF-8 string",
+			reason: e.to_string(),
+		})
+}
+
+async fn get_volume() -> Result<VolumeStats, Error> {
+	let contents = run_driver(&["--get-mute", "--get-volume"]).await?;
+	util::from_string(&PATTERN, &contents)
+}
+
+impl Volume {
+	/// Muted volume is shown in the theme's info colours.
+	fn render(&self, stats: &VolumeStats) -> Update {
+		let update = Update::new(self.format.render(&stats.values()));
+		match stats.mute {
+			1 => update.with_state(State::Info),
+			_ => update,
+		}
 	}
 }
 
 impl IntoStream for Volume {
-	fn into_stream(self) -> impl Stream<Item = Result<String, Error>> {
-		let mut signal_stream = signal(SignalKind::from_raw(self.update_signal))
-			.expect("failed to initialise Volume signal hook");
+	fn check(&self) -> Result<(), Error> {
+		self.format.check(VARIABLES)
+	}
+
+	async fn try_into_stream(
+		self,
+	) -> Result<impl Stream<Item = Result<impl Into<Update>, Error>>, Error> {
+		self.check()?;
+		let mut signal_stream = util::signal_stream(self.update_signal)?;
 		let duration = std::time::Duration::from_millis(self.period);
-		let re = regex::Regex::new(PATTERN).unwrap();
-		let mut command = Command::new(AUDIO_DRIVER_COMMAND);
-		command.args(["--get-mute", "--get-volume"]);
 
-		try_stream! {
+		Ok(try_stream! {
 			loop {
-				// Ignore the Result, it's fine if the timeout elapses
-				let _ = tokio::time::timeout(duration, signal_stream.recv()).await;
-				let contents = command.output()
-					.await
-					.map(|x| String::from_utf8(x.stdout))?
-					.map_err(|e| Error::Parse { ty: "UTF-8 string", reason: e.to_string() })?;
-				let stats: VolumeStats = util::from_string(&re, &contents)?;
-				yield format!("{}", stats);
+				pause::wait(Some(duration), signal_stream.recv()).await;
+				yield self.render(&get_volume().await?);
 			}
-		}
+		})
+	}
+}
+
+impl OnClick for Volume {
+	/// Left click toggles mute and scrolling changes the volume.
+	async fn on_click(&self, event: &ClickEvent) -> Result<Option<Update>, Error> {
+		let args: &[&str] = match event.button {
+			1 => &["--toggle-mute"],
+			4 => &["--change-volume", "+5"],
+			5 => &["--change-volume", "-5"],
+			_ => return Ok(None),
+		};
+		run_driver(args).await?;
+		let stats = get_volume().await?;
+		Ok(Some(self.render(&stats)))
 	}
 }
@@ -0,0 +1,189 @@
+use crate::blocks::BlockId;
+use crate::Error;
+use async_stream::stream;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures_util::Stream;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt, BufReader};
+
+/// A click event as sent by i3bar on stdin. See `output::Backend::click` for other bars.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ClickEvent {
+	pub name: String,
+	#[serde(default)]
+	pub instance: Option<String>,
+	pub button: u8,
+	#[serde(default)]
+	pub modifiers: Vec<String>,
+	#[serde(default)]
+	pub x: i32,
+	#[serde(default)]
+	pub y: i32,
+	#[serde(default)]
+	pub relative_x: i32,
+	#[serde(default)]
+	pub relative_y: i32,
+	#[serde(default)]
+	pub width: u32,
+	#[serde(default)]
+	pub height: u32,
+	/// The evdev code of the button, which only swaybar sends.
+	#[serde(default)]
+	pub event: Option<u32>,
+}
+
+impl ClickEvent {
+	/// The block the event targets.
+	pub fn id(&self) -> BlockId {
+		BlockId {
+			name: self.name.clone(),
+			instance: self.instance.clone(),
+		}
+	}
+}
+
+/// Parse a single line of the infinite JSON array i3bar writes to stdin.
+///
+/// The first line is the opening `[` and every event after the first is prefixed with a `,`, so
+/// both are stripped before parsing. Lines with no event on them yield `None`.
+fn parse_line(line: &str) -> Result<Option<ClickEvent>, Error> {
+	let line = line.trim().trim_start_matches(['[', ',']).trim();
+	if line.is_empty() {
+		return Ok(None);
+	}
+	serde_json::from_str(line)
+		.map(Some)
+		.map_err(Error::Serialize)
+}
+
+/// Read click events from stdin until it is closed.
+pub fn read_events() -> impl Stream<Item = Result<ClickEvent, Error>> {
+	events(BufReader::new(io::stdin()))
+}
+
+/// Read click events from `reader` until it is closed or fails. A malformed line yields an error
+/// and reading carries on, so that one bad event doesn't stop every later click.
+fn events<R: AsyncBufRead + Unpin>(reader: R) -> impl Stream<Item = Result<ClickEvent, Error>> {
+	stream! {
+		let mut lines = reader.lines();
+		loop {
+			match lines.next_line().await {
+				Ok(Some(line)) => {
+					if let Some(result) = parse_line(&line).transpose() {
+						yield result;
+					}
+				}
+				Ok(None) => break,
+				Err(e) => {
+					yield Err(Error::Io(e));
+					break;
+				}
+			}
+		}
+	}
+}
+
+/// Routes click events to the blocks they target.
+#[derive(Default)]
+pub struct Router {
+	senders: HashMap<BlockId, UnboundedSender<ClickEvent>>,
+}
+
+impl Router {
+	/// Register a block, returning the receiving end of its click events.
+	pub fn register(&mut self, id: BlockId) -> UnboundedReceiver<ClickEvent> {
+		let (tx, rx) = mpsc::unbounded();
+		self.senders.insert(id, tx);
+		rx
+	}
+
+	pub fn unregister(&mut self, id: &BlockId) {
+		self.senders.remove(id);
+	}
+
+	/// Send an event to the block it targets. Events for unknown blocks are dropped.
+	pub fn dispatch(&self, event: ClickEvent) {
+		if let Some(tx) = self.senders.get(&event.id()) {
+			// The receiver is only dropped once the block's stream has finished, in which case
+			// there's nothing left to react to the click
+			let _ = tx.unbounded_send(event);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse_lines() {
+		assert_eq!(parse_line("[").unwrap(), None);
+		assert_eq!(parse_line("").unwrap(), None);
+
+		let line = r#"{"name":"Volume","button":1,"modifiers":["Shift"],"x":1320,"y":1400,"relative_x":12,"relative_y":8,"width":50,"height":22}"#;
+		let event = parse_line(line).unwrap().unwrap();
+		assert_eq!(event.name, "Volume");
+		assert_eq!(event.instance, None);
+		assert_eq!(event.button, 1);
+		assert_eq!(event.modifiers, vec!["Shift".to_string()]);
+		assert_eq!((event.x, event.y), (1320, 1400));
+		assert_eq!((event.relative_x, event.relative_y), (12, 8));
+
+		let event = parse_line(r#",{"name":"Cpu","instance":"a","button":3}"#)
+			.unwrap()
+			.unwrap();
+		assert_eq!(event.name, "Cpu");
+		assert_eq!(event.instance.as_deref(), Some("a"));
+		assert_eq!(event.button, 3);
+	}
+
+	#[test]
+	fn invalid_line() {
+		assert!(parse_line(",{\"name\":").is_err());
+	}
+
+	#[tokio::test]
+	async fn skip_malformed_events() {
+		use futures_util::StreamExt;
+
+		let input = "[\n{\"name\":\n,{\"name\":\"Cpu\",\"button\":1}\n";
+		let events: Vec<_> = events(input.as_bytes()).collect().await;
+		assert_eq!(events.len(), 2);
+		assert!(events[0].is_err());
+		assert_eq!(events[1].as_ref().unwrap().name, "Cpu");
+	}
+
+	fn id(name: &str, instance: Option<&str>) -> BlockId {
+		BlockId {
+			name: name.to_string(),
+			instance: instance.map(String::from),
+		}
+	}
+
+	#[test]
+	fn dispatch_to_registered() {
+		let mut router = Router::default();
+		let mut rx = router.register(id("Volume", None));
+		let event = parse_line(r#"{"name":"Volume","button":4}"#)
+			.unwrap()
+			.unwrap();
+		router.dispatch(event.clone());
+		router.dispatch(parse_line(r#"{"name":"Cpu","button":1}"#).unwrap().unwrap());
+		assert_eq!(rx.try_recv().unwrap(), event);
+		assert!(rx.try_recv().is_err());
+	}
+
+	#[test]
+	fn dispatch_to_instance() {
+		let mut router = Router::default();
+		let mut wlan = router.register(id("Network", Some("wlan0")));
+		let mut eth = router.register(id("Network", Some("eth0")));
+		let event = parse_line(r#"{"name":"Network","instance":"eth0","button":1}"#)
+			.unwrap()
+			.unwrap();
+		router.dispatch(event.clone());
+		assert!(wlan.try_recv().is_err());
+		assert_eq!(eth.try_recv().unwrap(), event);
+	}
+}
@@ -1,43 +1,282 @@
-use crate::blocks::Block;
+use crate::blocks::{Attributes, Block, BlockId, Thresholds};
 use crate::error::Error;
+use crate::output::Output;
+use crate::theme::{Theme, ThemeConfig};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use toml::Value;
 
-fn map_block((name, value): (String, Value)) -> Result<Block, Error> {
-	// In scope to have access to `value`
+/// The name of the table holding settings for the bar as a whole rather than a single block.
+pub const SETTINGS: &str = "settings";
+
+/// The key giving the block type of a table which is named by the user, e.g.
+///
+/// ```toml
+/// [wired]
+/// type = "Network"
+/// ```
+pub const TYPE: &str = "type";
+
+/// The i3bar attribute used to tell apart several blocks of the same type.
+const INSTANCE: &str = "instance";
+
+/// Settings for the bar as a whole.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Settings {
+	/// Milliseconds to wait before restarting a block whose stream failed.
+	#[serde(default = "default_restart_delay")]
+	pub restart_delay: u64,
+	/// The upper limit on `restart_delay` as it backs off.
+	#[serde(default = "default_max_restart_delay")]
+	pub max_restart_delay: u64,
+	/// The factor `restart_delay` grows by each time a block fails without recovering.
+	#[serde(default = "default_restart_multiplier")]
+	pub restart_multiplier: f32,
+	/// The verbosity of logging, e.g. `info` or `debug`. See `logging::init`.
+	#[serde(default = "default_log_level")]
+	pub log_level: String,
+	/// A file to append logs to instead of writing them to stderr.
+	#[serde(default)]
+	pub log_file: Option<String>,
+	/// The protocol to write status lines in, unless given on the command line. Changing it needs a
+	/// restart.
+	#[serde(default)]
+	pub output: Output,
+	/// Milliseconds to wait after a block updates for others to update too, so that they share a
+	/// status line.
+	#[serde(default = "default_coalesce_window")]
+	pub coalesce_window: u64,
+	/// The most status lines printed a second, or 0 for no limit.
+	#[serde(default = "default_max_update_rate")]
+	pub max_update_rate: u32,
+	/// The icons and colours blocks are shown with. See `theme::ThemeConfig`.
+	#[serde(default)]
+	pub theme: ThemeConfig,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			restart_delay: default_restart_delay(),
+			max_restart_delay: default_max_restart_delay(),
+			restart_multiplier: default_restart_multiplier(),
+			log_level: default_log_level(),
+			log_file: None,
+			output: Output::default(),
+			coalesce_window: default_coalesce_window(),
+			max_update_rate: default_max_update_rate(),
+			theme: ThemeConfig::default(),
+		}
+	}
+}
+
+fn default_restart_delay() -> u64 {
+	1000
+}
+
+fn default_max_restart_delay() -> u64 {
+	60_000
+}
+
+fn default_restart_multiplier() -> f32 {
+	2.0
+}
+
+fn default_coalesce_window() -> u64 {
+	10
+}
+
+fn default_max_update_rate() -> u32 {
+	20
+}
+
+fn default_log_level() -> String {
+	"warn".to_string()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+	pub settings: Settings,
+	/// The theme resolved from `settings.theme`.
+	pub theme: Theme,
+	pub blocks: Vec<BlockConfig>,
+}
+
+impl Config {
+	/// Keep only the blocks matching `name`, which is either a block name or a full id of the form
+	/// `name/instance`.
+	pub fn only(mut self, name: &str) -> Result<Self, Error> {
+		self.blocks
+			.retain(|x| x.id().name == name || x.id().to_string() == name);
+		if self.blocks.is_empty() {
+			return Err(Error::BlockNotFound(name.to_string()));
+		}
+		Ok(self)
+	}
+}
+
+/// A block along with the i3bar attributes and thresholds configured for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockConfig {
+	pub block: Block,
+	pub attributes: Attributes,
+	pub thresholds: Thresholds,
+}
+
+impl BlockConfig {
+	pub fn id(&self) -> BlockId {
+		BlockId {
+			name: self.block.get_name().to_string(),
+			instance: self.attributes.instance.clone(),
+		}
+	}
+}
+
+/// The names of every block which can be configured.
+pub const BLOCK_NAMES: &[&str] = &[
+	"Battery",
+	"Brightness",
+	"Command",
+	"Cpu",
+	"Disk",
+	"DiskIo",
+	"Memory",
+	"Network",
+	"Temperature",
+	"Time",
+	"Volume",
+];
+
+/// Deserialise `T` from `value`, also returning the keys in `value` which `T` has no field for.
+pub fn deserialize_tracked<T: DeserializeOwned>(
+	value: Value,
+) -> Result<(T, Vec<String>), toml::de::Error> {
+	let mut ignored = Vec::new();
+	let deserialized = serde_ignored::deserialize(value, |path| ignored.push(path.to_string()))?;
+	Ok((deserialized, ignored))
+}
+
+/// Expand a top level entry of the config into the block types and tables it describes.
+///
+/// Besides a plain `[Network]` table, an entry can be an array of tables (`[[Network]]`) or a
+/// table with a user chosen name and a `type` key. Blocks from arrays are given their index as
+/// their instance and named tables are given their name, unless an instance is set explicitly.
+pub fn expand_entry((name, value): (String, Value)) -> Result<Vec<(String, Value)>, Error> {
+	let set_instance = |mut value: Value, instance: String| {
+		if let Value::Table(ref mut table) = value {
+			table
+				.entry(INSTANCE)
+				.or_insert_with(|| Value::String(instance));
+		}
+		value
+	};
+
+	match value {
+		Value::Array(values) => Ok(values
+			.into_iter()
+			.enumerate()
+			.map(|(i, value)| (name.clone(), set_instance(value, i.to_string())))
+			.collect()),
+		Value::Table(mut table) if table.contains_key(TYPE) => match table.remove(TYPE) {
+			Some(Value::String(ty)) => Ok(vec![(ty, set_instance(Value::Table(table), name))]),
+			_ => Err(Error::Deserialize {
+				name: "type",
+				reason: format!("the type of '{name}' must be a string"),
+			}),
+		},
+		value => Ok(vec![(name, value)]),
+	}
+}
+
+/// Deserialise a block, its attributes and its thresholds from its table, also returning any keys
+/// in the table which are used by none of them.
+pub fn map_block((name, value): (String, Value)) -> Result<(BlockConfig, Vec<String>), Error> {
+	let (attributes, mut unknown) =
+		deserialize_tracked(value.clone()).map_err(|e| Error::Deserialize {
+			name: "Attributes",
+			reason: e.to_string(),
+		})?;
+	let (thresholds, ignored): (Thresholds, _) =
+		deserialize_tracked(value.clone()).map_err(|e| Error::Deserialize {
+			name: "Thresholds",
+			reason: e.to_string(),
+		})?;
+	unknown.retain(|x| ignored.contains(x));
+
+	// In scope to have access to `value` and `unknown`
 	macro_rules! map_block_arm {
-		($name:ident) => {
-			Block::$name(value.try_into().map_err(|e| Error::Deserialize {
+		($name:ident) => {{
+			let (block, ignored) = deserialize_tracked(value).map_err(|e| Error::Deserialize {
 				name: stringify!($name),
 				reason: e.to_string(),
-			})?)
-		};
+			})?;
+			unknown.retain(|x| ignored.contains(x));
+			Block::$name(block)
+		}};
 	}
 
 	let block = match name.as_str() {
 		"Battery" => map_block_arm!(Battery),
 		"Brightness" => map_block_arm!(Brightness),
+		"Command" => map_block_arm!(Command),
 		"Cpu" => map_block_arm!(Cpu),
+		"Disk" => map_block_arm!(Disk),
+		"DiskIo" => map_block_arm!(DiskIo),
 		"Memory" => map_block_arm!(Memory),
 		"Network" => map_block_arm!(Network),
+		"Temperature" => map_block_arm!(Temperature),
 		"Time" => map_block_arm!(Time),
 		"Volume" => map_block_arm!(Volume),
 		_ => return Err(Error::InvalidBlockName(name)),
 	};
-	Ok(block)
+	let thresholds = thresholds.or(&block.default_thresholds());
+	Ok((
+		BlockConfig {
+			block,
+			attributes,
+			thresholds,
+		},
+		unknown,
+	))
 }
 
-pub fn deserialise(string: &str) -> Result<Vec<Block>, Error> {
+pub fn deserialise(string: &str) -> Result<Config, Error> {
 	// TODO: Investigate why deserialising directly into Map<String, Block> doesn't work
 	// With `Deserialize` implemented on `blocks::Block` there's an error about `DeserializeOwned`
 	// not being implemented.
 	// let deserialised: toml::map::Map<String, blocks::Block> = toml::from_str(string).unwrap();
-	let deserialised: toml::map::Map<String, Value> = toml::from_str(string)?;
-	deserialised.into_iter().map(map_block).collect()
+	let mut deserialised: toml::map::Map<String, Value> = toml::from_str(string)?;
+	let settings = match deserialised.remove(SETTINGS) {
+		Some(value) => value.try_into().map_err(|e| Error::Deserialize {
+			name: "Settings",
+			reason: e.to_string(),
+		})?,
+		None => Settings::default(),
+	};
+	let theme = settings.theme.load()?;
+	let mut blocks: Vec<BlockConfig> = Vec::new();
+	for entry in deserialised {
+		for entry in expand_entry(entry)? {
+			// Unknown keys are only reported when checking the config, see `validate`
+			let (block, _) = map_block(entry)?;
+			let id = block.id();
+			if blocks.iter().any(|x| x.id() == id) {
+				return Err(Error::DuplicateBlock(id.to_string()));
+			}
+			blocks.push(block);
+		}
+	}
+	Ok(Config {
+		settings,
+		theme,
+		blocks,
+	})
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::blocks::{Align, MinWidth};
 	use std::matches;
 
 	#[test]
@@ -47,12 +286,167 @@ mod test {
 			period = 10
 
 			[Battery]
+			path_to_charge_now = '/sys/class/power_supply/BAT0/energy_now'
+			path_to_charge_full = '/sys/class/power_supply/BAT0/energy_full'
+			path_to_status = '/sys/class/power_supply/BAT0/status'
 			period = 300
 			alpha = 0.1
 		";
 
-		let deserialised = deserialise(string).unwrap();
-		assert!(matches!(&deserialised[0], &Block::Volume(_)));
-		assert!(matches!(&deserialised[1], &Block::Battery(_)));
+		let deserialised = deserialise(string).unwrap().blocks;
+		assert!(matches!(&deserialised[0].block, &Block::Volume(_)));
+		assert!(matches!(&deserialised[1].block, &Block::Battery(_)));
+	}
+
+	#[test]
+	fn attributes() {
+		let string = "
+			[Cpu]
+			alpha = 0.2
+			color = '#ff0000'
+			min_width = 'CPU 100.0%'
+			align = 'right'
+			separator_block_width = 20
+
+			[Memory]
+			min_width = 80
+		";
+
+		let deserialised = deserialise(string).unwrap().blocks;
+		let attributes = &deserialised[0].attributes;
+		assert_eq!(attributes.color.as_deref(), Some("#ff0000"));
+		assert_eq!(
+			attributes.min_width,
+			Some(MinWidth::Text("CPU 100.0%".to_string()))
+		);
+		assert_eq!(attributes.align, Some(Align::Right));
+		assert_eq!(attributes.separator_block_width, Some(20));
+		assert_eq!(attributes.urgent, None);
+		assert_eq!(
+			deserialised[1].attributes.min_width,
+			Some(MinWidth::Pixels(80))
+		);
+	}
+
+	#[test]
+	fn settings() {
+		let string = "
+			[settings]
+			restart_delay = 200
+
+			[Time]
+		";
+
+		let config = deserialise(string).unwrap();
+		assert_eq!(config.settings.restart_delay, 200);
+		assert_eq!(
+			config.settings.max_restart_delay,
+			default_max_restart_delay()
+		);
+		assert_eq!(config.blocks.len(), 1);
+		assert_eq!(deserialise("").unwrap().settings, Settings::default());
+	}
+
+	#[test]
+	fn instances() {
+		let string = "
+			[[Network]]
+			path_to_rx = '/sys/class/net/wlan0/statistics/rx_bytes'
+			path_to_tx = '/sys/class/net/wlan0/statistics/tx_bytes'
+
+			[[Network]]
+			instance = 'eth0'
+			path_to_rx = '/sys/class/net/eth0/statistics/rx_bytes'
+			path_to_tx = '/sys/class/net/eth0/statistics/tx_bytes'
+
+			[london]
+			type = 'Time'
+			format = '%H:%M'
+
+			[Time]
+		";
+
+		let blocks = deserialise(string).unwrap().blocks;
+		let ids: Vec<_> = blocks.iter().map(|x| x.id().to_string()).collect();
+		assert_eq!(
+			ids,
+			vec!["Network/0", "Network/eth0", "Time/london", "Time"]
+		);
+		assert!(matches!(&blocks[2].block, &Block::Time(_)));
+	}
+
+	#[test]
+	fn every_block_name_maps() {
+		for name in BLOCK_NAMES {
+			let result = map_block((name.to_string(), Value::Table(Default::default())));
+			assert!(!matches!(result, Err(Error::InvalidBlockName(_))), "{name}");
+		}
+	}
+
+	#[test]
+	fn unknown_keys() {
+		let value = toml::from_str("perod = 10\nalpha = 0.2\ncolor = '#fff'").unwrap();
+		let (_, unknown) = map_block(("Cpu".to_string(), value)).unwrap();
+		assert_eq!(unknown, vec!["perod"]);
+	}
+
+	#[test]
+	fn thresholds() {
+		let string = "
+			[Cpu]
+			warning = 70
+			gradient = [[0, '#00ff00'], [100, '#ff0000']]
+
+			[Battery]
+			path_to_charge_now = '/sys/class/power_supply/BAT0/energy_now'
+			path_to_charge_full = '/sys/class/power_supply/BAT0/energy_full'
+			path_to_status = '/sys/class/power_supply/BAT0/status'
+			critical = 5
+		";
+		let blocks = deserialise(string).unwrap().blocks;
+		assert_eq!(blocks[0].thresholds.warning, Some(70.0));
+		assert_eq!(blocks[0].thresholds.critical, None);
+		assert_eq!(
+			blocks[0].thresholds.gradient.as_ref().map(Vec::len),
+			Some(2)
+		);
+		assert_eq!(blocks[1].thresholds.warning, Some(20.0));
+		assert_eq!(blocks[1].thresholds.critical, Some(5.0));
+	}
+
+	#[test]
+	fn only_given_block() {
+		let string = "
+			[Time]
+
+			[[Network]]
+			path_to_rx = 'rx'
+			path_to_tx = 'tx'
+
+			[[Network]]
+			path_to_rx = 'rx'
+			path_to_tx = 'tx'
+		";
+
+		let config = deserialise(string).unwrap();
+		assert_eq!(config.clone().only("Network").unwrap().blocks.len(), 2);
+		let blocks = config.clone().only("Network/1").unwrap().blocks;
+		assert_eq!(blocks.len(), 1);
+		assert_eq!(blocks[0].attributes.instance.as_deref(), Some("1"));
+		assert!(matches!(config.only("Cpu"), Err(Error::BlockNotFound(_))));
+	}
+
+	#[test]
+	fn duplicate_instances() {
+		let string = "
+			[[Time]]
+			instance = 'a'
+
+			[a]
+			type = 'Time'
+		";
+
+		let result = deserialise(string);
+		assert!(matches!(result, Err(Error::DuplicateBlock(id)) if id == "Time/a"));
 	}
 }
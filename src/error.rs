@@ -1,28 +1,47 @@
+use crate::args::USAGE;
 use std::io;
-
-const USAGE: &str = "Rust Blocks 0.1.0
-Lewis B. <gitlab.io/lewisbelcher>
-A simple i3blocks replacement written in Rust.
-
-USAGE:
-    rs-blocks <CONFIG>
-
-ARGS:
-    <CONFIG>         Config file to use
-";
+use std::process::ExitStatus;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+	#[error("there is no block '{0}' in the config")]
+	BlockNotFound(String),
+	#[error("command '{command}' failed ({status}): {stderr}")]
+	Command {
+		command: String,
+		status: ExitStatus,
+		stderr: String,
+	},
 	#[error("failed to deserialise block '{name}': {reason}")]
 	Deserialize { name: &'static str, reason: String },
+	#[error("block '{0}' is configured more than once")]
+	DuplicateBlock(String),
 	#[error("no block implemented for '{0}'")]
 	InvalidBlockName(String),
+	#[error("found {0} error(s) in the config")]
+	InvalidConfig(usize),
 	#[error(transparent)]
 	Io(#[from] io::Error),
+	#[error("failed to set up logging: {0}")]
+	Logging(String),
+	#[error("there is no device '{0}'")]
+	NoDevice(String),
+	#[error("found no temperature sensors {0}")]
+	NoSensors(String),
 	#[error("error while parsing to type '{ty}': {reason}")]
 	Parse { ty: &'static str, reason: String },
+	#[error("failed to read '{path}': {source}")]
+	Read { path: String, source: io::Error },
+	#[error(transparent)]
+	Regex(#[from] regex::Error),
+	#[error("failed to reload config: {0}")]
+	Reload(String),
 	#[error(transparent)]
 	Serialize(#[from] serde_json::Error),
+	#[error("failed to set up {name}: {reason}")]
+	Setup { name: &'static str, reason: String },
+	#[error("failed to register a handler for signal {signal}: {source}")]
+	Signal { signal: i32, source: io::Error },
 	#[error(transparent)]
 	Toml(#[from] toml::de::Error),
 	#[error("{}", USAGE)]
@@ -0,0 +1,34 @@
+use crate::config::Settings;
+use crate::Error;
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Start logging according to the given settings, with `level` taking precedence over the one in
+/// the settings when given.
+///
+/// Stdout carries the i3bar protocol, so logs go to `log_file` if set and stderr otherwise. The
+/// level is anything accepted as a tracing `EnvFilter`, e.g. `debug` or
+/// `warn,rs_blocks_tokio=trace`. Logging is only set up once, so later changes to these settings
+/// need a restart.
+pub fn init(settings: &Settings, level: Option<&str>) -> Result<(), Error> {
+	let level = level.unwrap_or(&settings.log_level);
+	let filter = EnvFilter::try_new(level)
+		.map_err(|e| Error::Logging(format!("invalid log level '{level}': {e}")))?;
+	let builder = tracing_subscriber::fmt().with_env_filter(filter);
+	match &settings.log_file {
+		Some(path) => {
+			let file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.map_err(|e| Error::Logging(format!("failed to open '{path}': {e}")))?;
+			builder
+				.with_ansi(false)
+				.with_writer(Mutex::new(file))
+				.init();
+		}
+		None => builder.with_writer(std::io::stderr).init(),
+	}
+	Ok(())
+}
@@ -1,49 +1,184 @@
-use futures_util::{stream::SelectAll, StreamExt};
-use indexmap::IndexMap;
-use itertools::Itertools;
+use args::{Args, Mode};
+use futures_util::{pin_mut, StreamExt};
+use output::{Backend, Scheduler, CONT_SIGNAL, STOP_SIGNAL};
 use std::fs;
+use std::process::ExitCode;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{timeout, Duration, Instant};
 
 pub mod args;
+pub mod bar;
 pub mod blocks;
+pub mod click;
 pub mod config;
 pub mod error;
+pub mod logging;
+pub mod output;
+pub mod supervisor;
+pub mod theme;
+pub mod validate;
 
 pub use error::Error;
 
-fn initialise_output_map(block_vec: &[blocks::Block]) -> IndexMap<String, String> {
-	block_vec
+/// The longest `--once` waits for every block to output something before printing what it has.
+const ONCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the config has to go without being saved again before it's reloaded, since some
+/// editors save in several steps.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+fn read_config(args: &Args) -> Result<String, Error> {
+	fs::read_to_string(&args.config_path).map_err(|source| Error::Read {
+		path: args.config_path.clone(),
+		source,
+	})
+}
+
+fn load_config(args: &Args) -> Result<config::Config, Error> {
+	let config = config::deserialise(&read_config(args)?)?;
+	match &args.block {
+		Some(name) => config.only(name),
+		None => Ok(config),
+	}
+}
+
+/// Reload the config, applying its settings for when lines are printed as well as its blocks.
+fn reload(bar: &mut bar::Bar, scheduler: &mut Scheduler, args: &Args) {
+	bar.reload(load_config(args));
+	let settings = bar.settings();
+	scheduler.configure(
+		Duration::from_millis(settings.coalesce_window),
+		settings.max_update_rate,
+	);
+}
+
+/// Print every problem found in the config, failing if any of them are errors.
+fn check(args: &Args) -> Result<(), Error> {
+	let source = read_config(args)?;
+	let diagnostics = validate::validate(&source);
+	for diagnostic in &diagnostics {
+		eprintln!("{}", diagnostic.render(&args.config_path, &source));
+	}
+	let errors = diagnostics
 		.iter()
-		.map(|block| (block.get_name().to_string(), "{}".to_string()))
-		.collect()
+		.filter(|x| x.severity == validate::Severity::Error)
+		.count();
+	if errors > 0 {
+		return Err(Error::InvalidConfig(errors));
+	}
+	println!("{}: ok", args.config_path);
+	Ok(())
 }
 
-fn print_preamble() {
-	println!("{{\"version\":1,\"click_events\":true}}");
-	println!("[");
+/// Print a single status line once every block has output something, or once `ONCE_TIMEOUT`
+/// has passed if some are slow to.
+async fn once(mut bar: bar::Bar, backend: &dyn Backend) {
+	let complete = async {
+		while !bar.is_complete() {
+			let res = bar.next().await;
+			bar.update(res);
+		}
+	};
+	// Ignore the Result, whatever has been output by the timeout is printed
+	let _ = timeout(ONCE_TIMEOUT, complete).await;
+	println!("{}", bar.status_line(backend, false));
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), error::Error> {
-	let args = args::parse_args()?;
-	let config_str = fs::read_to_string(args.config_path)?;
-	let block_vec = config::deserialise(&config_str)?;
-	let mut output_map = initialise_output_map(&block_vec);
-
-	// TODO: Why doesn't FuturesUnordered work?
-	// let futures: FuturesUnordered<_> = block_vec
-	let mut futures: SelectAll<_> = block_vec
-		.into_iter()
-		.map(|block| block.into_stream_pin())
-		.collect();
-	print_preamble();
+async fn run(args: Args) -> Result<(), Error> {
+	if args.mode == Mode::Check {
+		return check(&args);
+	}
+	let config = load_config(&args)?;
+	logging::init(&config.settings, args.log_level.as_deref())?;
+	let backend = args.output.unwrap_or(config.settings.output).backend();
+	let mut scheduler = Scheduler::new(
+		Duration::from_millis(config.settings.coalesce_window),
+		config.settings.max_update_rate,
+	);
+	let mut bar = bar::Bar::new(config);
+	if args.mode == Mode::Once {
+		once(bar, backend.as_ref()).await;
+		return Ok(());
+	}
+
+	let mut hangup = signal(SignalKind::hangup())?;
+	let mut stop = blocks::util::signal_stream(STOP_SIGNAL as i32)?;
+	let mut cont = blocks::util::signal_stream(CONT_SIGNAL as i32)?;
+	let mut stopped = false;
+	let mut config_file = blocks::notify::WatchedFile::open_saved(&args.config_path).await?;
+	let clicks = click::read_events();
+	pin_mut!(clicks);
+	if let Some(preamble) = backend.preamble() {
+		println!("{preamble}");
+	}
 	loop {
-		let res = futures.select_next_some().await?;
-		output_map.insert(res.block_name, res.text);
-		let print: String = output_map
-			.values()
-			.filter_map(|x| if x != "{}" { Some(x.as_str()) } else { None })
-			.intersperse(",")
-			.collect();
-		println!("[{}],", print);
+		tokio::select! {
+			() = scheduler.due() => {
+				let line = bar.status_line(backend.as_ref(), true);
+				if let Some(line) = scheduler.take(line, Instant::now()) {
+					println!("{line}");
+				}
+				continue;
+			}
+			res = bar.next() => {
+				bar.update(res);
+			}
+			Some(event) = clicks.next() => {
+				// A malformed event shouldn't take down the bar, so just skip it
+				match event {
+					Ok(event) => bar.dispatch(backend.click(event)),
+					Err(e) => tracing::warn!(error = %e, "skipping malformed click event"),
+				}
+				continue;
+			}
+			Some(()) = hangup.recv() => {
+				reload(&mut bar, &mut scheduler, &args);
+			}
+			() = config_file.changed() => {
+				while timeout(RELOAD_DEBOUNCE, config_file.changed()).await.is_ok() {}
+				reload(&mut bar, &mut scheduler, &args);
+			}
+			Some(()) = stop.recv() => {
+				tracing::info!("bar stopped");
+				stopped = true;
+				blocks::pause::set_stopped(true);
+				scheduler.cancel();
+				continue;
+			}
+			Some(()) = cont.recv() => {
+				tracing::info!("bar continued");
+				stopped = false;
+				blocks::pause::set_stopped(false);
+				// Blocks refresh on continuing, which schedules a line
+				continue;
+			}
+		}
+		// Nothing is shown while stopped
+		if !stopped {
+			scheduler.changed(Instant::now());
+		}
+	}
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+	let result = match args::parse_args() {
+		Ok(args) if args.mode == Mode::Help => {
+			print!("{}", args::USAGE);
+			Ok(())
+		}
+		Ok(args) if args.mode == Mode::Version => {
+			println!("rs-blocks {}", env!("CARGO_PKG_VERSION"));
+			Ok(())
+		}
+		Ok(args) => run(args).await,
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("{e}");
+			ExitCode::FAILURE
+		}
 	}
 }
@@ -0,0 +1,88 @@
+use crate::blocks::Serialized;
+use crate::click::ClickEvent;
+use crate::output::Backend;
+use nix::sys::signal::Signal;
+
+/// The signals i3bar is asked to send when it hides and shows the bar, in place of SIGSTOP and
+/// SIGCONT. Unlike SIGSTOP these can be handled, so that blocks can pause and refresh on resuming.
+pub const STOP_SIGNAL: Signal = Signal::SIGTSTP;
+pub const CONT_SIGNAL: Signal = Signal::SIGCONT;
+
+/// i3bar's JSON protocol: a header, then an endless array with an array of blocks per line.
+pub struct I3bar;
+
+impl Backend for I3bar {
+	fn preamble(&self) -> Option<String> {
+		Some(format!(
+			"{{\"version\":1,\"click_events\":true,\"stop_signal\":{},\"cont_signal\":{}}}\n[",
+			STOP_SIGNAL as i32, CONT_SIGNAL as i32
+		))
+	}
+
+	fn status_line(&self, blocks: &[&Serialized], streaming: bool) -> String {
+		// Blocks have no maps with non-string keys, which is all that can fail to serialise
+		let line = serde_json::to_string(blocks).unwrap_or_default();
+		if streaming {
+			line + ","
+		} else {
+			line
+		}
+	}
+}
+
+/// swaybar speaks i3bar's protocol, but its click events carry the evdev code of the button as
+/// `event`, with `button` left as 0 for buttons X11 has no number for.
+pub struct Swaybar;
+
+/// evdev's mouse button codes and their X11 numbers.
+const BUTTONS: &[(u32, u8)] = &[
+	(0x110, 1), // BTN_LEFT
+	(0x111, 3), // BTN_RIGHT
+	(0x112, 2), // BTN_MIDDLE
+	(0x113, 8), // BTN_SIDE
+	(0x114, 9), // BTN_EXTRA
+];
+
+impl Backend for Swaybar {
+	fn preamble(&self) -> Option<String> {
+		I3bar.preamble()
+	}
+
+	fn status_line(&self, blocks: &[&Serialized], streaming: bool) -> String {
+		I3bar.status_line(blocks, streaming)
+	}
+
+	fn click(&self, mut event: ClickEvent) -> ClickEvent {
+		if event.button == 0 {
+			if let Some((_, button)) = BUTTONS.iter().find(|(code, _)| Some(*code) == event.event) {
+				event.button = *button;
+			}
+		}
+		event
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::output::block;
+
+	#[test]
+	fn i3bar() {
+		let cpu = block("Cpu", "50%");
+		assert_eq!(
+			I3bar.status_line(&[&cpu], true),
+			r#"[{"name":"Cpu","full_text":"50%"}],"#
+		);
+		assert_eq!(I3bar.status_line(&[], false), "[]");
+		assert!(I3bar.preamble().unwrap().contains("\"stop_signal\":20"));
+	}
+
+	#[test]
+	fn swaybar_clicks() {
+		let click = |line: &str| Swaybar.click(serde_json::from_str(line).unwrap()).button;
+		assert_eq!(click(r#"{"name":"Cpu","button":0,"event":275}"#), 8);
+		assert_eq!(click(r#"{"name":"Cpu","button":4,"event":768}"#), 4);
+		assert_eq!(click(r#"{"name":"Cpu","button":1}"#), 1);
+	}
+}
@@ -0,0 +1,65 @@
+use crate::blocks::Serialized;
+use crate::output::{plain_text, Backend};
+use serde::Serialize;
+
+/// A JSON array of blocks per line, with plain text and each block's value and state, so other
+/// tools needn't understand i3bar's protocol or Pango.
+pub struct JsonLines;
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Block<'a> {
+	name: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	instance: Option<&'a str>,
+	text: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	value: Option<f64>,
+	state: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	color: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	background: Option<&'a str>,
+	urgent: bool,
+}
+
+impl<'a> From<&'a Serialized> for Block<'a> {
+	fn from(block: &'a Serialized) -> Self {
+		Self {
+			name: block.name,
+			instance: block.attributes.instance.as_deref(),
+			text: plain_text(block),
+			value: block.value,
+			state: block.state.name(),
+			color: block.attributes.color.as_deref(),
+			background: block.attributes.background.as_deref(),
+			urgent: block.attributes.urgent == Some(true),
+		}
+	}
+}
+
+impl Backend for JsonLines {
+	fn status_line(&self, blocks: &[&Serialized], _streaming: bool) -> String {
+		let blocks: Vec<Block> = blocks.iter().map(|x| Block::from(*x)).collect();
+		serde_json::to_string(&blocks).unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::blocks::State;
+	use crate::output::block;
+
+	#[test]
+	fn json_lines() {
+		let mut cpu = block("Cpu", "50%");
+		cpu.value = Some(50.0);
+		cpu.state = State::Critical;
+		cpu.attributes.urgent = Some(true);
+		cpu.attributes.instance = Some("0".to_string());
+		assert_eq!(
+			JsonLines.status_line(&[&cpu], true),
+			r#"[{"name":"Cpu","instance":"0","text":"50%","value":50.0,"state":"critical","urgent":true}]"#
+		);
+	}
+}
@@ -0,0 +1,185 @@
+use crate::blocks::Serialized;
+use crate::click::ClickEvent;
+use serde::Deserialize;
+use std::str::FromStr;
+
+pub mod i3bar;
+pub mod json;
+pub mod scheduler;
+pub mod text;
+pub mod waybar;
+
+pub use i3bar::{I3bar, Swaybar, CONT_SIGNAL, STOP_SIGNAL};
+pub use json::JsonLines;
+pub use scheduler::Scheduler;
+pub use text::{Dzen2, Lemonbar, Plain};
+pub use waybar::Waybar;
+
+/// Writes status lines for a particular bar.
+///
+/// Blocks produce i3bar's blocks, which each backend converts to what its bar understands,
+/// including the text's markup and colours.
+pub trait Backend {
+	/// Printed once before any status lines.
+	fn preamble(&self) -> Option<String> {
+		None
+	}
+
+	/// A status line made of `blocks`. When `streaming` is false the line is the only one printed,
+	/// as with `--once`.
+	fn status_line(&self, blocks: &[&Serialized], streaming: bool) -> String;
+
+	/// Convert a click event read from stdin to i3bar's conventions, which blocks expect.
+	fn click(&self, event: ClickEvent) -> ClickEvent {
+		event
+	}
+}
+
+/// The protocol status lines are written in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+	/// i3bar's JSON protocol.
+	#[default]
+	I3bar,
+	/// i3bar's JSON protocol, with swaybar's click events.
+	Swaybar,
+	/// The JSON lines a waybar custom module reads with `return-type = "json"`.
+	Waybar,
+	/// Plain text, e.g. for tmux or `xsetroot -name`.
+	Plain,
+	/// Text with lemonbar's formatting tags.
+	Lemonbar,
+	/// Text with dzen2's formatting commands.
+	Dzen2,
+	/// A JSON array of blocks per line, for piping into other tools.
+	Json,
+}
+
+const OUTPUTS: &[(&str, Output)] = &[
+	("i3bar", Output::I3bar),
+	("swaybar", Output::Swaybar),
+	("waybar", Output::Waybar),
+	("plain", Output::Plain),
+	("lemonbar", Output::Lemonbar),
+	("dzen2", Output::Dzen2),
+	("json", Output::Json),
+];
+
+impl FromStr for Output {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match OUTPUTS.iter().find(|(name, _)| *name == s) {
+			Some((_, output)) => Ok(*output),
+			None => {
+				let names: Vec<_> = OUTPUTS.iter().map(|(name, _)| *name).collect();
+				Err(format!(
+					"unknown output '{s}', expected one of: {}",
+					names.join(", ")
+				))
+			}
+		}
+	}
+}
+
+impl Output {
+	pub fn backend(self) -> Box<dyn Backend> {
+		match self {
+			Output::I3bar => Box::new(I3bar),
+			Output::Swaybar => Box::new(Swaybar),
+			Output::Waybar => Box::new(Waybar),
+			Output::Plain => Box::new(Plain),
+			Output::Lemonbar => Box::new(Lemonbar),
+			Output::Dzen2 => Box::new(Dzen2),
+			Output::Json => Box::new(JsonLines),
+		}
+	}
+}
+
+/// The text of a block without any markup, for bars which don't understand Pango.
+pub fn plain_text(block: &Serialized) -> String {
+	if block.markup != Some("pango") {
+		return block.full_text.clone();
+	}
+	let mut text = String::new();
+	let mut in_tag = false;
+	for c in block.full_text.chars() {
+		match c {
+			'<' => in_tag = true,
+			'>' if in_tag => in_tag = false,
+			c if !in_tag => text.push(c),
+			_ => {}
+		}
+	}
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+/// The text of a block as Pango markup, escaping the text of blocks which don't use markup and
+/// wrapping it in a span with the block's colours.
+pub fn pango_text(block: &Serialized) -> String {
+	let text = match block.markup {
+		Some("pango") => block.full_text.clone(),
+		_ => block
+			.full_text
+			.replace('&', "&amp;")
+			.replace('<', "&lt;")
+			.replace('>', "&gt;"),
+	};
+	let mut attributes = String::new();
+	if let Some(color) = &block.attributes.color {
+		attributes += &format!(" foreground='{color}'");
+	}
+	if let Some(background) = &block.attributes.background {
+		attributes += &format!(" background='{background}'");
+	}
+	match attributes.is_empty() {
+		true => text,
+		false => format!("<span{attributes}>{text}</span>"),
+	}
+}
+
+#[cfg(test)]
+pub(crate) fn block(name: &'static str, text: &str) -> Serialized {
+	Serialized {
+		name,
+		full_text: text.to_string(),
+		markup: None,
+		attributes: Default::default(),
+		value: None,
+		state: Default::default(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse_outputs() {
+		assert_eq!("dzen2".parse(), Ok(Output::Dzen2));
+		assert!("i3".parse::<Output>().is_err());
+	}
+
+	#[test]
+	fn markup() {
+		let mut cpu = block("Cpu", "<b>50%</b> &amp; <i>a &lt; b</i>");
+		assert_eq!(
+			pango_text(&cpu),
+			"&lt;b&gt;50%&lt;/b&gt; &amp;amp; &lt;i&gt;a &amp;lt; b&lt;/i&gt;"
+		);
+		assert_eq!(plain_text(&cpu), cpu.full_text);
+		cpu.markup = Some("pango");
+		assert_eq!(plain_text(&cpu), "50% & a < b");
+		assert_eq!(pango_text(&cpu), cpu.full_text);
+		cpu.attributes.color = Some("#ffcc00".to_string());
+		assert_eq!(
+			pango_text(&cpu),
+			format!("<span foreground='#ffcc00'>{}</span>", cpu.full_text)
+		);
+	}
+}
@@ -0,0 +1,125 @@
+use std::future;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// Decides when status lines are printed. Blocks updating within `window` of each other share a
+/// line, lines are at least `interval` apart and a line the same as the last is dropped, so the
+/// bar only redraws when there's something new to show.
+#[derive(Debug)]
+pub struct Scheduler {
+	window: Duration,
+	interval: Duration,
+	deadline: Option<Instant>,
+	printed: Option<Instant>,
+	last: Option<String>,
+}
+
+impl Scheduler {
+	/// A scheduler printing at most `max_rate` lines a second, without a limit if it's 0.
+	pub fn new(window: Duration, max_rate: u32) -> Self {
+		let mut scheduler = Self {
+			window,
+			interval: Duration::ZERO,
+			deadline: None,
+			printed: None,
+			last: None,
+		};
+		scheduler.configure(window, max_rate);
+		scheduler
+	}
+
+	/// Change the window and rate, e.g. when the config is reloaded. A line already due keeps its
+	/// deadline.
+	pub fn configure(&mut self, window: Duration, max_rate: u32) {
+		self.window = window;
+		self.interval = match max_rate {
+			0 => Duration::ZERO,
+			rate => Duration::from_secs(1) / rate,
+		};
+	}
+
+	/// Note that the bar changed at `now`. A line is due at the end of the window, or once the
+	/// interval since the last line has passed if that's later. A line already due isn't delayed.
+	pub fn changed(&mut self, now: Instant) {
+		if self.deadline.is_none() {
+			let earliest = self.printed.map_or(now, |x| x + self.interval);
+			self.deadline = Some((now + self.window).max(earliest));
+		}
+	}
+
+	/// Wait until a line is due, which is forever if none is.
+	pub async fn due(&self) {
+		match self.deadline {
+			Some(deadline) => sleep_until(deadline).await,
+			None => future::pending().await,
+		}
+	}
+
+	/// Forget any line that's due, e.g. when the bar is hidden.
+	pub fn cancel(&mut self) {
+		self.deadline = None;
+	}
+
+	/// Take the status line which was due at `now`, returning it to be printed unless it's the
+	/// same as the last one.
+	pub fn take(&mut self, line: String, now: Instant) -> Option<String> {
+		self.deadline = None;
+		if self.last.as_ref() == Some(&line) {
+			return None;
+		}
+		self.printed = Some(now);
+		self.last = Some(line.clone());
+		Some(line)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const MS: Duration = Duration::from_millis(1);
+
+	#[test]
+	fn coalesces_and_limits() {
+		let start = Instant::now();
+		let mut scheduler = Scheduler::new(10 * MS, 10);
+
+		// Updates within the window share one line
+		scheduler.changed(start);
+		scheduler.changed(start + 5 * MS);
+		assert_eq!(scheduler.deadline, Some(start + 10 * MS));
+		assert_eq!(
+			scheduler.take("a".to_string(), start + 10 * MS),
+			Some("a".to_string())
+		);
+		assert_eq!(scheduler.deadline, None);
+
+		// The next line waits out the interval
+		scheduler.changed(start + 20 * MS);
+		assert_eq!(scheduler.deadline, Some(start + 110 * MS));
+
+		// An unchanged line is dropped and doesn't count towards the rate
+		assert_eq!(scheduler.take("a".to_string(), start + 110 * MS), None);
+		scheduler.changed(start + 200 * MS);
+		assert_eq!(scheduler.deadline, Some(start + 210 * MS));
+
+		scheduler.cancel();
+		assert_eq!(scheduler.deadline, None);
+	}
+
+	#[test]
+	fn unlimited() {
+		let start = Instant::now();
+		let mut scheduler = Scheduler::new(Duration::ZERO, 0);
+		scheduler.changed(start);
+		assert_eq!(scheduler.deadline, Some(start));
+		assert!(scheduler.take("a".to_string(), start).is_some());
+		scheduler.changed(start);
+		assert_eq!(scheduler.deadline, Some(start));
+
+		// Limits set on reload apply from the next line
+		scheduler.take("b".to_string(), start);
+		scheduler.configure(10 * MS, 10);
+		scheduler.changed(start);
+		assert_eq!(scheduler.deadline, Some(start + 100 * MS));
+	}
+}
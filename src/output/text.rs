@@ -0,0 +1,79 @@
+use crate::blocks::Serialized;
+use crate::output::{plain_text, Backend};
+use itertools::Itertools;
+
+/// What goes between blocks on bars which only take a line of text.
+const SEPARATOR: &str = " | ";
+
+/// Plain text, dropping colours and markup.
+pub struct Plain;
+
+impl Backend for Plain {
+	fn status_line(&self, blocks: &[&Serialized], _streaming: bool) -> String {
+		blocks.iter().map(|x| plain_text(x)).join(SEPARATOR)
+	}
+}
+
+/// Text with lemonbar's `%{F#rrggbb}` style tags for colours.
+pub struct Lemonbar;
+
+impl Backend for Lemonbar {
+	fn status_line(&self, blocks: &[&Serialized], _streaming: bool) -> String {
+		let block = |block: &&Serialized| {
+			let mut text = plain_text(block).replace('%', "%%");
+			if let Some(color) = &block.attributes.color {
+				text = format!("%{{F{color}}}{text}%{{F-}}");
+			}
+			if let Some(background) = &block.attributes.background {
+				text = format!("%{{B{background}}}{text}%{{B-}}");
+			}
+			text
+		};
+		blocks.iter().map(block).join(SEPARATOR)
+	}
+}
+
+/// Text with dzen2's `^fg(#rrggbb)` style commands for colours.
+pub struct Dzen2;
+
+impl Backend for Dzen2 {
+	fn status_line(&self, blocks: &[&Serialized], _streaming: bool) -> String {
+		let block = |block: &&Serialized| {
+			let mut text = plain_text(block).replace('^', "^^");
+			if let Some(color) = &block.attributes.color {
+				text = format!("^fg({color}){text}^fg()");
+			}
+			if let Some(background) = &block.attributes.background {
+				text = format!("^bg({background}){text}^bg()");
+			}
+			text
+		};
+		blocks.iter().map(block).join(SEPARATOR)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::output::block;
+
+	#[test]
+	fn text_backends() {
+		let mut cpu = block("Cpu", "<b>50%</b>");
+		cpu.markup = Some("pango");
+		cpu.attributes.color = Some("#ffcc00".to_string());
+		let mut time = block("Time", "^12:00");
+		time.attributes.background = Some("#000000".to_string());
+		let blocks = [&cpu, &time];
+
+		assert_eq!(Plain.status_line(&blocks, true), "50% | ^12:00");
+		assert_eq!(
+			Lemonbar.status_line(&blocks, true),
+			"%{F#ffcc00}50%%%{F-} | %{B#000000}^12:00%{B-}"
+		);
+		assert_eq!(
+			Dzen2.status_line(&blocks, true),
+			"^fg(#ffcc00)50%^fg() | ^bg(#000000)^^12:00^bg()"
+		);
+	}
+}
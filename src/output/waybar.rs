@@ -0,0 +1,85 @@
+use crate::blocks::Serialized;
+use crate::output::{pango_text, Backend};
+use itertools::Itertools;
+use serde::Serialize;
+
+/// A waybar custom module's JSON lines, one object per status line.
+pub struct Waybar;
+
+/// A waybar custom module's output. The text and tooltip are Pango markup.
+#[derive(Debug, PartialEq, Serialize)]
+struct Module {
+	text: String,
+	tooltip: String,
+	class: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	percentage: Option<u8>,
+	alt: &'static str,
+}
+
+impl Module {
+	/// Combine `blocks` into the one module. A single block maps straight onto the module, whereas
+	/// several are shown side by side with the most severe state of any of them.
+	fn new(blocks: &[&Serialized]) -> Self {
+		let state = blocks.iter().map(|x| x.state).max().unwrap_or_default();
+		let mut class: Vec<String> = blocks
+			.iter()
+			.map(|x| x.name.to_lowercase())
+			.unique()
+			.collect();
+		class.push(state.name().to_string());
+		if blocks.iter().any(|x| x.attributes.urgent == Some(true)) {
+			class.push("urgent".to_string());
+		}
+		let percentage = match blocks {
+			[block] => block.value.map(|x| x.clamp(0.0, 100.0).round() as u8),
+			_ => None,
+		};
+		Self {
+			text: blocks.iter().map(|x| pango_text(x)).join("  "),
+			tooltip: blocks
+				.iter()
+				.map(|x| format!("{}: {}", x.name, pango_text(x)))
+				.join("\n"),
+			class,
+			percentage,
+			alt: state.name(),
+		}
+	}
+}
+
+impl Backend for Waybar {
+	fn status_line(&self, blocks: &[&Serialized], _streaming: bool) -> String {
+		serde_json::to_string(&Module::new(blocks)).unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::blocks::State;
+	use crate::output::block;
+
+	#[test]
+	fn waybar() {
+		let mut cpu = block("Cpu", "<50%>");
+		cpu.value = Some(50.4);
+		cpu.state = State::Warning;
+		assert_eq!(
+			Waybar.status_line(&[&cpu], true),
+			r#"{"text":"&lt;50%&gt;","tooltip":"Cpu: &lt;50%&gt;","class":["cpu","warning"],"percentage":50,"alt":"warning"}"#
+		);
+
+		let time = block("Time", "12:00");
+		let line = Waybar.status_line(&[&cpu, &time], true);
+		assert_eq!(
+			serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+			serde_json::json!({
+				"text": "&lt;50%&gt;  12:00",
+				"tooltip": "Cpu: &lt;50%&gt;\nTime: 12:00",
+				"class": ["cpu", "time", "warning"],
+				"alt": "warning",
+			})
+		);
+	}
+}
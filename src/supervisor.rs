@@ -0,0 +1,170 @@
+use crate::blocks::stream_ext::StreamExt2;
+use crate::blocks::BlockResult;
+use crate::click::ClickEvent;
+use crate::config::{BlockConfig, Settings};
+use crate::Error;
+use async_stream::stream;
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures_util::{Stream, StreamExt};
+use std::pin::Pin;
+use tokio::time::{sleep, Duration, Instant};
+
+/// How many times its next restart delay a block has to stay up for to count as having recovered.
+const RECOVERY_FACTOR: u32 = 4;
+
+/// Exponentially increasing delays between restarts of a failing block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backoff {
+	initial: Duration,
+	max: Duration,
+	multiplier: f32,
+	current: Duration,
+}
+
+impl Backoff {
+	pub fn new(settings: &Settings) -> Self {
+		let initial = Duration::from_millis(settings.restart_delay);
+		Self {
+			initial,
+			max: Duration::from_millis(settings.max_restart_delay),
+			multiplier: settings.restart_multiplier,
+			current: initial,
+		}
+	}
+
+	/// Return the delay to wait before the next restart and back off for the one after.
+	pub fn next_delay(&mut self) -> Duration {
+		let delay = self.current;
+		self.current = self.current.mul_f32(self.multiplier).min(self.max);
+		delay
+	}
+
+	/// Return to the initial delay, used once a block has recovered.
+	pub fn reset(&mut self) {
+		self.current = self.initial;
+	}
+
+	/// Whether a block which ran for `uptime` before failing had recovered. A block which fails
+	/// soon after each restart, even if it updates first, has to keep backing off.
+	pub fn recovered(&self, uptime: Duration) -> bool {
+		uptime >= self.current * RECOVERY_FACTOR
+	}
+}
+
+// Events only live for one turn of the loop, so there's nothing to gain from boxing results
+#[allow(clippy::large_enum_variant)]
+enum Event {
+	Result(Option<Result<BlockResult, Error>>),
+	Click(ClickEvent),
+}
+
+/// Run a block, isolating its failures from the rest of the bar.
+///
+/// Whenever the block's stream yields an error, the error is shown in the block's place and the
+/// stream is restarted from the block's config after a backoff delay. Everything the block logs is
+/// recorded under a `block` span carrying its id.
+pub fn supervise(
+	config: BlockConfig,
+	mut clicks: UnboundedReceiver<ClickEvent>,
+	settings: &Settings,
+) -> Pin<Box<dyn Stream<Item = BlockResult>>> {
+	let mut backoff = Backoff::new(settings);
+	// Spans below the configured level are disabled along with their fields, so the span is at the
+	// highest level to make sure every event from the block is attributed to it
+	let span = tracing::error_span!("block", id = %config.id());
+	let stream = stream! {
+		loop {
+			// Each run of the block gets its own channel so that clicks survive restarts
+			let (tx, rx) = mpsc::unbounded();
+			let started = Instant::now();
+			let mut results = config
+				.block
+				.clone()
+				.into_stream_pin(config.attributes.clone(), config.thresholds.clone(), rx);
+			loop {
+				let event = tokio::select! {
+					result = results.next() => Event::Result(result),
+					Some(click) = clicks.next() => Event::Click(click),
+				};
+				match event {
+					Event::Result(Some(Ok(result))) => {
+						tracing::debug!(text = %result.block.full_text, "update");
+						yield result;
+					}
+					Event::Result(Some(Err(e))) => {
+						tracing::error!(error = %e, "block failed");
+						yield BlockResult::error(config.block.get_name(), &e, &config.attributes);
+						break;
+					}
+					Event::Result(None) => {
+						tracing::info!("block finished");
+						break;
+					}
+					Event::Click(click) => {
+						tracing::debug!(button = click.button, "click");
+						let _ = tx.unbounded_send(click);
+					}
+				}
+			}
+			if backoff.recovered(started.elapsed()) {
+				backoff.reset();
+			}
+			let delay = backoff.next_delay();
+			tracing::info!(delay_ms = delay.as_millis() as u64, "restarting");
+			sleep(delay).await;
+		}
+	};
+	Box::pin(stream.instrument(span))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::blocks::Block;
+
+	#[test]
+	fn backoff_grows_to_max() {
+		let settings = Settings {
+			restart_delay: 100,
+			max_restart_delay: 500,
+			restart_multiplier: 2.0,
+			..Settings::default()
+		};
+		let mut backoff = Backoff::new(&settings);
+		let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+		assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+		backoff.reset();
+		assert_eq!(backoff.next_delay().as_millis(), 100);
+		assert!(!backoff.recovered(Duration::from_millis(700)));
+		assert!(backoff.recovered(Duration::from_millis(1000)));
+	}
+
+	#[tokio::test]
+	async fn backoff_after_update() {
+		let settings = Settings {
+			restart_delay: 50,
+			max_restart_delay: 1000,
+			restart_multiplier: 2.0,
+			..Settings::default()
+		};
+		let config = BlockConfig {
+			block: Block::Command(
+				toml::from_str("command = 'echo one; exit 1'\npersist = true").unwrap(),
+			),
+			attributes: Default::default(),
+			thresholds: Default::default(),
+		};
+		let (_tx, rx) = mpsc::unbounded();
+		let mut results = supervise(config, rx, &settings);
+		// Each run updates once before failing, which mustn't count as the block having recovered
+		let mut starts = Vec::new();
+		while starts.len() < 4 {
+			let result = results.next().await.unwrap();
+			if result.block.full_text == "one" {
+				starts.push(Instant::now());
+			}
+		}
+		let last = starts[3] - starts[2];
+		assert!(last >= Duration::from_millis(200), "{last:?}");
+	}
+}
@@ -0,0 +1,333 @@
+use crate::blocks::thresholds::{Rgb, State};
+use crate::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+/// The theme blocks currently look their icons and colours up in, see `set`.
+static THEME: RwLock<Option<Arc<Theme>>> = RwLock::new(None);
+
+/// The built in sets of icons.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconSet {
+	/// Glyphs from a Nerd Font.
+	#[default]
+	Nerd,
+	/// Glyphs from Font Awesome 5.
+	Awesome,
+	/// Plain text, for fonts without icons.
+	Ascii,
+	Emoji,
+}
+
+impl IconSet {
+	/// The icon for each key in the set. Every set has the same keys.
+	fn icons(self) -> &'static [(&'static str, &'static str)] {
+		match self {
+			IconSet::Nerd => &[
+				("battery_charging", "\u{f5e7}"),
+				("battery_empty", "\u{f244}"),
+				("battery_full", "\u{f240}"),
+				("battery_half", "\u{f242}"),
+				("battery_quarter", "\u{f243}"),
+				("battery_three_quarters", "\u{f241}"),
+				("brightness", "\u{f042}"),
+				("cpu", "\u{f2db}"),
+				("disk", "\u{f0a0}"),
+				("memory", "\u{f538}"),
+				("net_down", "\u{f0ab}"),
+				("net_up", "\u{f0aa}"),
+				("temperature", "\u{f2c9}"),
+				("volume", "\u{f028}"),
+				("volume_muted", "\u{f026}"),
+			],
+			IconSet::Awesome => &[
+				("battery_charging", "\u{f0e7}"),
+				("battery_empty", "\u{f244}"),
+				("battery_full", "\u{f240}"),
+				("battery_half", "\u{f242}"),
+				("battery_quarter", "\u{f243}"),
+				("battery_three_quarters", "\u{f241}"),
+				("brightness", "\u{f185}"),
+				("cpu", "\u{f2db}"),
+				("disk", "\u{f0a0}"),
+				("memory", "\u{f538}"),
+				("net_down", "\u{f063}"),
+				("net_up", "\u{f062}"),
+				("temperature", "\u{f2c9}"),
+				("volume", "\u{f028}"),
+				("volume_muted", "\u{f6a9}"),
+			],
+			IconSet::Ascii => &[
+				("battery_charging", "CHR"),
+				("battery_empty", "BAT"),
+				("battery_full", "BAT"),
+				("battery_half", "BAT"),
+				("battery_quarter", "BAT"),
+				("battery_three_quarters", "BAT"),
+				("brightness", "BRI"),
+				("cpu", "CPU"),
+				("disk", "DSK"),
+				("memory", "MEM"),
+				("net_down", "v"),
+				("net_up", "^"),
+				("temperature", "TMP"),
+				("volume", "VOL"),
+				("volume_muted", "MUTE"),
+			],
+			IconSet::Emoji => &[
+				("battery_charging", "\u{1f50c}"),
+				("battery_empty", "\u{1faab}"),
+				("battery_full", "\u{1f50b}"),
+				("battery_half", "\u{1f50b}"),
+				("battery_quarter", "\u{1faab}"),
+				("battery_three_quarters", "\u{1f50b}"),
+				("brightness", "\u{2600}"),
+				("cpu", "\u{1f5a5}"),
+				("disk", "\u{1f4be}"),
+				("memory", "\u{1f9e0}"),
+				("net_down", "\u{2b07}"),
+				("net_up", "\u{2b06}"),
+				("temperature", "\u{1f321}"),
+				("volume", "\u{1f50a}"),
+				("volume_muted", "\u{1f507}"),
+			],
+		}
+	}
+}
+
+/// The foreground and background colours of a state.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Colours {
+	pub fg: Option<Rgb>,
+	pub bg: Option<Rgb>,
+}
+
+impl Colours {
+	fn or(self, defaults: Colours) -> Colours {
+		Colours {
+			fg: self.fg.or(defaults.fg),
+			bg: self.bg.or(defaults.bg),
+		}
+	}
+}
+
+/// The colours of each state a block can be in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+	pub idle: Colours,
+	pub info: Colours,
+	pub good: Colours,
+	pub warning: Colours,
+	pub critical: Colours,
+}
+
+impl Palette {
+	/// Idle is left to the bar's own colours.
+	fn builtin() -> Self {
+		let colours = |fg, bg| Colours { fg, bg };
+		Self {
+			idle: colours(None, None),
+			info: colours(Some(Rgb(0x88, 0xc0, 0xd0)), None),
+			good: colours(Some(Rgb(0xa3, 0xbe, 0x8c)), None),
+			warning: colours(Some(Rgb(0xff, 0xcc, 0x00)), None),
+			critical: colours(Some(Rgb(0xff, 0xff, 0xff)), Some(Rgb(0xcc, 0x00, 0x00))),
+		}
+	}
+
+	fn or(self, defaults: Palette) -> Palette {
+		Palette {
+			idle: self.idle.or(defaults.idle),
+			info: self.info.or(defaults.info),
+			good: self.good.or(defaults.good),
+			warning: self.warning.or(defaults.warning),
+			critical: self.critical.or(defaults.critical),
+		}
+	}
+
+	pub fn colours(&self, state: State) -> Colours {
+		match state {
+			State::Idle => self.idle,
+			State::Info => self.info,
+			State::Good => self.good,
+			State::Warning => self.warning,
+			State::Critical => self.critical,
+		}
+	}
+}
+
+/// The `theme` table of the settings, which can also be kept in a file of its own:
+///
+/// ```toml
+/// [settings.theme]
+/// icons = "awesome"
+/// file = "/home/me/.config/rs-blocks/theme.toml"
+///
+/// [settings.theme.icon]
+/// cpu = "C"
+///
+/// [settings.theme.palette.warning]
+/// fg = "#ebcb8b"
+/// ```
+///
+/// A theme file has the same keys, other than `file`. Anything set in the settings takes
+/// precedence over the file, which in turn takes precedence over the built in icons and palette.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+	/// The built in set of icons to start from.
+	pub icons: Option<IconSet>,
+	/// Icons replacing those of the icon set, by key.
+	#[serde(default)]
+	pub icon: HashMap<String, String>,
+	#[serde(default)]
+	pub palette: Palette,
+	pub file: Option<String>,
+}
+
+impl ThemeConfig {
+	fn or(mut self, defaults: ThemeConfig) -> ThemeConfig {
+		for (key, icon) in defaults.icon {
+			self.icon.entry(key).or_insert(icon);
+		}
+		ThemeConfig {
+			icons: self.icons.or(defaults.icons),
+			icon: self.icon,
+			palette: self.palette.or(defaults.palette),
+			file: self.file,
+		}
+	}
+
+	/// Resolve the theme, reading its file if there is one.
+	pub fn load(&self) -> Result<Theme, Error> {
+		let config = match &self.file {
+			Some(path) => {
+				let string = fs::read_to_string(path).map_err(|source| Error::Read {
+					path: path.clone(),
+					source,
+				})?;
+				let file: ThemeConfig =
+					toml::from_str(&string).map_err(|e| Error::Deserialize {
+						name: "Theme",
+						reason: e.to_string(),
+					})?;
+				self.clone().or(file)
+			}
+			None => self.clone(),
+		};
+		let mut icons: HashMap<String, String> = config
+			.icons
+			.unwrap_or_default()
+			.icons()
+			.iter()
+			.map(|(key, icon)| (key.to_string(), icon.to_string()))
+			.collect();
+		icons.extend(config.icon);
+		Ok(Theme {
+			icons,
+			palette: config.palette.or(Palette::builtin()),
+		})
+	}
+}
+
+/// The icons and colours blocks are shown with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+	icons: HashMap<String, String>,
+	pub palette: Palette,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		// The built in theme has no file to fail to read
+		ThemeConfig::default().load().unwrap()
+	}
+}
+
+impl Theme {
+	/// The icon for `key`, which is empty for keys the theme doesn't know.
+	pub fn icon(&self, key: &str) -> &str {
+		self.icons.get(key).map_or("", String::as_str)
+	}
+}
+
+/// Make `theme` the one blocks use from now on. Blocks look the theme up each time they update, so
+/// a reloaded theme shows without restarting them.
+pub fn set(theme: Theme) {
+	*THEME.write().unwrap() = Some(Arc::new(theme));
+}
+
+/// The current theme, which is the built in one until another is set.
+pub fn current() -> Arc<Theme> {
+	THEME
+		.read()
+		.unwrap()
+		.clone()
+		.unwrap_or_else(|| Arc::new(Theme::default()))
+}
+
+/// The icon for `key` in the current theme.
+pub fn icon(key: &str) -> String {
+	current().icon(key).to_string()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn load(string: &str) -> Result<Theme, Error> {
+		toml::from_str::<ThemeConfig>(string).unwrap().load()
+	}
+
+	#[test]
+	fn icon_sets() {
+		let nerd = IconSet::Nerd.icons().iter().map(|x| x.0);
+		for set in [IconSet::Awesome, IconSet::Ascii, IconSet::Emoji] {
+			assert!(nerd.clone().eq(set.icons().iter().map(|x| x.0)), "{set:?}");
+		}
+		assert_eq!(load("icons = 'ascii'").unwrap().icon("cpu"), "CPU");
+		assert_eq!(Theme::default().icon("cpu"), "\u{f2db}");
+		assert_eq!(Theme::default().icon("unknown"), "");
+	}
+
+	#[test]
+	fn overrides() {
+		let theme = load(
+			"
+			icons = 'ascii'
+			icon = { cpu = 'C' }
+			palette.critical.fg = '#000000'
+			",
+		)
+		.unwrap();
+		assert_eq!(theme.icon("cpu"), "C");
+		assert_eq!(theme.icon("memory"), "MEM");
+		let critical = theme.palette.colours(State::Critical);
+		assert_eq!(critical.fg, Some(Rgb(0, 0, 0)));
+		assert_eq!(critical.bg, Some(Rgb(0xcc, 0, 0)));
+	}
+
+	#[test]
+	fn theme_file() {
+		let path = std::env::temp_dir().join("rs-blocks-theme-file.toml");
+		fs::write(&path, "icons = 'emoji'\nicon = { cpu = 'C', memory = 'M' }").unwrap();
+		let theme = load(&format!(
+			"file = '{}'\nicon = {{ memory = 'RAM' }}",
+			path.display()
+		))
+		.unwrap();
+		assert_eq!(theme.icon("cpu"), "C");
+		assert_eq!(theme.icon("memory"), "RAM");
+		assert_eq!(theme.icon("volume"), "\u{1f50a}");
+		fs::remove_file(path).unwrap();
+
+		assert!(matches!(
+			load("file = '/nonexistent.toml'"),
+			Err(Error::Read { .. })
+		));
+	}
+}
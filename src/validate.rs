@@ -0,0 +1,367 @@
+use crate::blocks::BlockId;
+use crate::config::{self, Settings, BLOCK_NAMES, SETTINGS, TYPE};
+use crate::Error;
+use itertools::Itertools;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use std::path::Path;
+use toml::Value;
+use toml_edit::{ImDocument, Item, Key, TableLike};
+
+/// How close a misspelt block name has to be to a real one for it to be suggested.
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+	/// The bar won't start with this config.
+	Error,
+	/// The bar will start, but probably not as intended.
+	Warning,
+}
+
+impl Display for Severity {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Severity::Error => write!(f, "error"),
+			Severity::Warning => write!(f, "warning"),
+		}
+	}
+}
+
+/// A problem found in a config along with where it is, as a byte range of the config.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub span: Option<Range<usize>>,
+	pub message: String,
+}
+
+impl Diagnostic {
+	/// Messages from the TOML parser can span several lines, which are joined to keep to one line
+	/// per diagnostic.
+	fn new(severity: Severity, span: Option<Range<usize>>, message: &str) -> Self {
+		Self {
+			severity,
+			span,
+			message: message
+				.lines()
+				.map(str::trim)
+				.filter(|x| !x.is_empty())
+				.join(", "),
+		}
+	}
+
+	fn error(span: Option<Range<usize>>, message: impl AsRef<str>) -> Self {
+		Self::new(Severity::Error, span, message.as_ref())
+	}
+
+	fn warning(span: Option<Range<usize>>, message: impl AsRef<str>) -> Self {
+		Self::new(Severity::Warning, span, message.as_ref())
+	}
+
+	/// Render as `path:line:column: severity: message`, which editors know how to jump to.
+	pub fn render(&self, path: &str, source: &str) -> String {
+		match &self.span {
+			Some(span) => {
+				let (line, column) = position(source, span.start);
+				format!(
+					"{path}:{line}:{column}: {}: {}",
+					self.severity, self.message
+				)
+			}
+			None => format!("{path}: {}: {}", self.severity, self.message),
+		}
+	}
+}
+
+/// The 1-based line and column of a byte offset into `source`.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+	let before = &source[..offset.min(source.len())];
+	let line = before.matches('\n').count() + 1;
+	let column = before.chars().rev().take_while(|&x| x != '\n').count() + 1;
+	(line, column)
+}
+
+/// The block name closest to `name`, if there's one close enough to be a likely typo.
+fn suggest(name: &str) -> Option<&'static str> {
+	BLOCK_NAMES
+		.iter()
+		.map(|x| {
+			(
+				strsim::jaro_winkler(&name.to_lowercase(), &x.to_lowercase()),
+				*x,
+			)
+		})
+		.filter(|(score, _)| *score >= SUGGESTION_THRESHOLD)
+		.max_by(|a, b| a.0.total_cmp(&b.0))
+		.map(|(_, x)| x)
+}
+
+/// The tables a top level item holds, which is several for an array of tables.
+fn tables(item: &Item) -> Vec<&dyn TableLike> {
+	match item {
+		Item::ArrayOfTables(array) => array.iter().map(|x| x as &dyn TableLike).collect(),
+		item => item.as_table_like().into_iter().collect(),
+	}
+}
+
+/// The span of `key` in `table`, falling back to `default` for keys the table doesn't have.
+fn key_span(
+	table: &dyn TableLike,
+	key: &str,
+	default: Option<Range<usize>>,
+) -> Option<Range<usize>> {
+	table
+		.get_key_value(key)
+		.and_then(|(key, _)| key.span())
+		.or(default)
+}
+
+/// Reports the problems in each part of a config.
+#[derive(Default)]
+struct Validator {
+	diagnostics: Vec<Diagnostic>,
+	ids: Vec<BlockId>,
+}
+
+impl Validator {
+	fn settings(&mut self, key: &Key, item: &Item, value: Value) {
+		let table = tables(item).pop();
+		match config::deserialize_tracked::<Settings>(value) {
+			Ok((settings, unknown)) => {
+				for name in unknown {
+					let span = table.and_then(|x| key_span(x, &name, key.span()));
+					let message = format!("unknown setting `{name}`");
+					self.diagnostics.push(Diagnostic::error(span, message));
+				}
+				// A theme file is only read when the config is loaded, so is checked here too
+				if let Err(e) = settings.theme.load() {
+					let theme = table.and_then(|x| x.get_key_value("theme"));
+					let span = theme
+						.and_then(|(_, item)| item.as_table_like())
+						.and_then(|x| key_span(x, "file", None))
+						.or(theme.and_then(|(key, _)| key.span()))
+						.or(key.span());
+					let message = format!("invalid theme: {e}");
+					self.diagnostics.push(Diagnostic::error(span, message));
+				}
+			}
+			Err(e) => {
+				let message = format!("invalid settings: {}", e.message());
+				self.diagnostics
+					.push(Diagnostic::error(key.span(), message));
+			}
+		}
+	}
+
+	fn block(&mut self, key: &Key, table: Option<&dyn TableLike>, entry: (String, Value)) {
+		let span = key.span();
+		let name = entry.0.clone();
+		let (block, unknown) = match config::map_block(entry) {
+			Ok(mapped) => mapped,
+			Err(Error::InvalidBlockName(name)) => {
+				// The name comes from the `type` key for named tables
+				let span = table
+					.and_then(|x| x.get(TYPE))
+					.and_then(Item::span)
+					.or(span);
+				let mut message = format!("no block implemented for '{name}'");
+				if let Some(suggestion) = suggest(&name) {
+					message += &format!(", did you mean '{suggestion}'?");
+				}
+				self.diagnostics.push(Diagnostic::error(span, message));
+				return;
+			}
+			Err(e) => {
+				self.diagnostics
+					.push(Diagnostic::error(span, e.to_string()));
+				return;
+			}
+		};
+
+		for field in unknown {
+			let span = table.and_then(|x| key_span(x, &field, span.clone()));
+			let message = format!("unknown field `{field}` for block '{name}'");
+			self.diagnostics.push(Diagnostic::error(span, message));
+		}
+
+		// Problems with a block's format are most likely in `format`, so are reported there
+		if let Err(e) = block.block.check() {
+			let span = ["format", "short_format"]
+				.iter()
+				.find_map(|x| table.and_then(|t| key_span(t, x, None)))
+				.or(span.clone());
+			let message = format!("invalid format for block '{name}': {e}");
+			self.diagnostics.push(Diagnostic::error(span, message));
+		}
+
+		let id = block.id();
+		if self.ids.contains(&id) {
+			let message = Error::DuplicateBlock(id.to_string()).to_string();
+			self.diagnostics.push(Diagnostic::error(span, message));
+		}
+		self.ids.push(id);
+
+		// Paths on sysfs and procfs are set by the kernel rather than the user, so one which
+		// doesn't exist is almost certainly wrong. Only warn though, as hardware can come and go.
+		for (_, item) in table.into_iter().flat_map(|x| x.iter()) {
+			if let Some(path) = item.as_str() {
+				if (path.starts_with("/sys/") || path.starts_with("/proc/"))
+					&& !Path::new(path).exists()
+				{
+					let message = format!("'{path}' does not exist");
+					self.diagnostics
+						.push(Diagnostic::warning(item.span(), message));
+				}
+			}
+		}
+	}
+}
+
+/// Some parse errors come without a message, in which case there's at least the location.
+fn syntax_error(span: Option<Range<usize>>, message: &str) -> Diagnostic {
+	match message.trim() {
+		"" => Diagnostic::error(span, "invalid TOML"),
+		message => Diagnostic::error(span, message),
+	}
+}
+
+/// Check a config, reporting every problem in it rather than stopping at the first as
+/// `config::deserialise` does. Besides errors which would stop the config from loading, this
+/// reports keys which would otherwise be silently ignored, formats which would fail when the block
+/// is set up, theme files which can't be loaded and sysfs/procfs paths which don't exist.
+pub fn validate(source: &str) -> Vec<Diagnostic> {
+	let document = match ImDocument::parse(source) {
+		Ok(document) => document,
+		Err(e) => return vec![syntax_error(e.span(), e.message())],
+	};
+	let values: toml::map::Map<String, Value> = match toml::from_str(source) {
+		Ok(values) => values,
+		Err(e) => return vec![syntax_error(e.span(), e.message())],
+	};
+
+	let mut validator = Validator::default();
+	for (name, value) in values {
+		// Every key in `values` was parsed from the same document
+		let Some((key, item)) = document.as_table().get_key_value(&name) else {
+			continue;
+		};
+		if name == SETTINGS {
+			validator.settings(key, item, value);
+			continue;
+		}
+		match config::expand_entry((name, value)) {
+			Ok(entries) => {
+				let tables = tables(item);
+				for (i, entry) in entries.into_iter().enumerate() {
+					validator.block(key, tables.get(i).copied(), entry);
+				}
+			}
+			Err(e) => {
+				let diagnostic = Diagnostic::error(key.span(), e.to_string());
+				validator.diagnostics.push(diagnostic);
+			}
+		}
+	}
+	validator.diagnostics
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn render(source: &str) -> Vec<String> {
+		validate(source)
+			.iter()
+			.map(|x| x.render("config.toml", source))
+			.collect()
+	}
+
+	#[test]
+	fn valid_config() {
+		assert_eq!(
+			render("[settings]\nrestart_delay = 10\n\n[Time]\n[Cpu]"),
+			Vec::<String>::new()
+		);
+	}
+
+	#[test]
+	fn syntax_error() {
+		assert_eq!(
+			render("[Time]\nformat = %H"),
+			vec!["config.toml:2:10: error: invalid string, expected `\"`, `'`"]
+		);
+	}
+
+	#[test]
+	fn every_problem_is_reported() {
+		let source = "[settings]
+restart_dealy = 10
+
+[Cpuu]
+
+[[Cpu]]
+perod = 500
+
+[wired]
+type = 'Netwrok'
+
+[Memory]
+meminfo_path = '/proc/nonexistent'
+";
+		assert_eq!(
+			render(source),
+			vec![
+				"config.toml:2:1: error: unknown setting `restart_dealy`",
+				"config.toml:4:2: error: no block implemented for 'Cpuu', did you mean 'Cpu'?",
+				"config.toml:7:1: error: unknown field `perod` for block 'Cpu'",
+				"config.toml:10:8: error: no block implemented for 'Netwrok', did you mean 'Network'?",
+				"config.toml:13:16: warning: '/proc/nonexistent' does not exist",
+			]
+		);
+	}
+
+	#[test]
+	fn invalid_block() {
+		assert_eq!(
+			render("[Cpu]\nperiod = 'fast'\n"),
+			vec!["config.toml:1:2: error: failed to deserialise block 'Cpu': invalid type: string \"fast\", expected u64, in `period`"]
+		);
+	}
+
+	#[test]
+	fn invalid_format() {
+		let source = "[Cpu]\nformat = '{foo}'\n\n[Time]\nformat = '%Q %H'\n";
+		assert_eq!(
+			render(source),
+			vec![
+				"config.toml:2:1: error: invalid format for block 'Cpu': error while parsing to type 'Template': unknown placeholder '{foo}', expected one of: icon, percent, cores, max_percent, history, frequency",
+				"config.toml:5:1: error: invalid format for block 'Time': error while parsing to type 'strftime format': invalid format '%Q %H'",
+			]
+		);
+	}
+
+	#[test]
+	fn invalid_theme() {
+		assert_eq!(
+			render("[settings.theme]\nfile = '/nonexistent'\n"),
+			vec!["config.toml:2:1: error: invalid theme: failed to read '/nonexistent': No such file or directory (os error 2)"]
+		);
+	}
+
+	#[test]
+	fn duplicate_block() {
+		let source = "[a]\ntype = 'Time'\ninstance = 'x'\n\n[b]\ntype = 'Time'\ninstance = 'x'\n";
+		assert_eq!(
+			render(source),
+			vec!["config.toml:5:2: error: block 'Time/x' is configured more than once"]
+		);
+	}
+
+	#[test]
+	fn no_suggestion_for_unrelated_names() {
+		assert_eq!(suggest("Cpuu"), Some("Cpu"));
+		assert_eq!(suggest("memory"), Some("Memory"));
+		assert_eq!(suggest("Weather"), None);
+	}
+}